@@ -1,5 +1,6 @@
 use advent::AdventSolver;
 use failure::Error;
+use util::grid::{Dir,IPos};
 
 const TARGET: usize = 347991;
 
@@ -10,7 +11,7 @@ impl AdventSolver for Solver {
     fn solve(&mut self) -> Result<(), Error> {
         // Part 1: Simple spiral of 1, 2, 3...
         let mut spiral1 = Spiral::new();
-        let mut pos: (isize, isize) = (0, 0);
+        let mut pos = IPos::origin();
         for value in 1..TARGET+1 {
             if value == TARGET {
                 break;
@@ -18,12 +19,12 @@ impl AdventSolver for Solver {
             pos = spiral1.append(value);
         }
         println!("{} occurs at ({}, {}), dist: {}",
-                 TARGET, pos.0, pos.1, pos.0.abs()+pos.1.abs());
+                 TARGET, pos.col, pos.row, pos.manhattan(IPos::origin()));
 
         // Part 2: Spiral using sum_of_neighbors at each position.
         let mut spiral2 = Spiral::new();
         let mut value: usize = 1;
-        let mut pos: (isize, isize) = spiral2.append(1);
+        let mut pos = spiral2.append(1);
         while value <= TARGET {
             value = spiral2.sum_of_neighbors(pos);
             pos = spiral2.append(value);
@@ -33,9 +34,11 @@ impl AdventSolver for Solver {
     }
 }
 
+// Positions are printed as (x, y), x being the column and y the row, with y
+// growing downwards.
 struct Spiral {
-    bounds: (isize, isize),
-    cur_pos: (isize, isize),
+    bounds: isize,
+    cur_pos: IPos,
     rows: Vec<Vec<usize>>
 }
 
@@ -45,51 +48,53 @@ impl Spiral {
         rows.push(vec![0]);
 
         Spiral {
-            bounds: (0, 0),
-            cur_pos: (0, 0),
+            bounds: 0,
+            cur_pos: IPos::origin(),
             rows: rows
         }
     }
 
     // Add a new value onto the spiral. Returns the position at which the value
     // was added.
-    pub fn append(&mut self, value: usize) -> (isize, isize) {
+    pub fn append(&mut self, value: usize) -> IPos {
         // Write the value at the current position.
         let pos = self.cur_pos;
         self.set(pos, value);
 
         // Advance current position and grow if needed.
         self.cur_pos = self.next_pos(self.cur_pos);
-        if self.cur_pos.0 > self.bounds.0 {
-            let new_bound = self.cur_pos.0;
+        if self.cur_pos.col > self.bounds {
+            let new_bound = self.cur_pos.col;
             self.grow(new_bound);
         }
         self.cur_pos
     }
 
     // Access a value at a position. Out of bounds will return None.
-    pub fn get(&self, pos: (isize, isize)) -> Option<usize> {
-        let index = (Self::pos_to_index(pos.0), Self::pos_to_index(pos.1));
-        if index.1 < self.rows.len() {
-            let row = &self.rows[index.1];
-            if index.0 < row.len() {
-                return Some(row[index.0]);
+    pub fn get(&self, pos: IPos) -> Option<usize> {
+        let (row, col) = (Self::pos_to_index(pos.row),
+                          Self::pos_to_index(pos.col));
+        if row < self.rows.len() {
+            let row = &self.rows[row];
+            if col < row.len() {
+                return Some(row[col]);
             }
         }
         None
     }
 
     // Write a value at a position. Doesn't grow spiral to fit.
-    pub fn set(&mut self, pos: (isize, isize), value: usize) {
-        let index = (Self::pos_to_index(pos.0), Self::pos_to_index(pos.1));
-        self.rows[index.1][index.0] = value;
+    pub fn set(&mut self, pos: IPos, value: usize) {
+        let (row, col) = (Self::pos_to_index(pos.row),
+                          Self::pos_to_index(pos.col));
+        self.rows[row][col] = value;
     }
 
     // Increase the spiral size so that its bounded at -bound..bound in both
     // dimensions. E.g. spirals start with bound == 0 (1x1), then grow to
     // bound=1 (3x3), bound=2 (5x5).
     fn grow(&mut self, bound: isize) {
-        self.bounds = (bound, bound);
+        self.bounds = bound;
         let size: usize = (bound.abs() as usize) * 2 + 1;
         for row in &mut self.rows {
             if row.len() < size {
@@ -101,31 +106,32 @@ impl Spiral {
         }
     }
 
-    fn next_pos(&self, pos: (isize, isize)) -> (isize, isize) {
+    fn next_pos(&self, pos: IPos) -> IPos {
+        let bound = self.bounds;
         // Bottom edge
-        if pos.1 == self.bounds.1 {
+        if pos.row == bound {
             // May exceed self.bounds, caller should grow if necessary
-            (pos.0+1, pos.1)
+            pos.neighbor(Dir::Right)
         // Right edge
-        } else if pos.0 == self.bounds.0 {
-            if pos.1 > -self.bounds.1 {
-                (pos.0, pos.1-1)
+        } else if pos.col == bound {
+            if pos.row > -bound {
+                pos.neighbor(Dir::Up)
             } else {
-                (pos.0-1, pos.1)
+                pos.neighbor(Dir::Left)
             }
         // Top edge
-        } else if pos.1 == -self.bounds.1 {
-            if pos.0 > -self.bounds.0 {
-                (pos.0-1, pos.1)
+        } else if pos.row == -bound {
+            if pos.col > -bound {
+                pos.neighbor(Dir::Left)
             } else {
-                (pos.0, pos.1+1)
+                pos.neighbor(Dir::Down)
             }
         // Left edge
-        } else /* pos.0 == -self.bounds.0 */ {
-            if pos.1 < self.bounds.1 {
-                (pos.0, pos.1+1)
+        } else /* pos.col == -bound */ {
+            if pos.row < bound {
+                pos.neighbor(Dir::Down)
             } else {
-                (pos.0+1, pos.1)
+                pos.neighbor(Dir::Right)
             }
         }
     }
@@ -153,20 +159,7 @@ impl Spiral {
         }
     }
 
-    fn sum_of_neighbors(&self, pos: (isize, isize)) -> usize {
-        let offsets: [(isize, isize); 8] = [
-            (-1, -1), ( 0, -1), ( 1, -1),
-            (-1,  0),           ( 1,  0),
-            (-1,  1), ( 0,  1), ( 1,  1)
-        ];
-        let mut result: usize = 0;
-        for offset in offsets.into_iter() {
-            let neighbor_pos = (pos.0 + offset.0, pos.1 + offset.1);
-            match self.get(neighbor_pos) {
-                Some(value) => result += value,
-                None => {}
-            }
-        }
-        result
+    fn sum_of_neighbors(&self, pos: IPos) -> usize {
+        pos.neighbors8().filter_map(|n| self.get(n)).sum()
     }
 }
@@ -1,10 +1,9 @@
 use advent::AdventSolver;
 use failure::Error;
 use std::cmp::max;
-use std::fmt;
 use std::fs::File;
 use std::io::Read;
-use std::str::FromStr;
+use util::hex::{Direction,HexPoint};
 
 #[derive(Default)]
 pub struct Solver;
@@ -34,91 +33,3 @@ impl AdventSolver for Solver {
         Ok(())
     }
 }
-
-// Directions in a flat-topped hex space.
-enum Direction {
-    N,
-    NE,
-    SE,
-    S,
-    SW,
-    NW
-}
-
-impl FromStr for Direction {
-    type Err = Error;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "n"  => Ok(Direction::N),
-            "ne" => Ok(Direction::NE),
-            "se" => Ok(Direction::SE),
-            "s"  => Ok(Direction::S),
-            "sw" => Ok(Direction::SW),
-            "nw" => Ok(Direction::NW),
-            _    => Err(format_err!("bad direction: {}", s))
-        }
-    }
-}
-
-// Axial-coordinate point in hex space.
-// For what it's worth, I'm choosing these axes:
-//
-//   +y           -z
-//      ↖ _____ ↗
-//       ╱     ╲
-//      ╱       ╲
-// -x ← ╲       ╱ →  +x
-//       ╲_____╱
-//      ↙       ↘
-//    +z          -y
-//
-// z will always be calculated from x and y.
-#[derive(Clone, Copy)]
-struct HexPoint {
-    x: isize,
-    y: isize,
-    z: isize
-}
-
-impl HexPoint {
-    pub fn new(x: isize, y: isize) -> Self {
-        HexPoint {
-            x: x,
-            y: y,
-            z: 0 - x - y
-        }
-    }
-
-    pub fn origin() -> Self {
-        HexPoint {
-            x: 0,
-            y: 0,
-            z: 0
-        }
-    }
-
-    pub fn neighbor(&self, dir: Direction) -> HexPoint {
-        match dir {
-            Direction::N  => HexPoint::new(self.x,   self.y+1),
-            Direction::NE => HexPoint::new(self.x+1, self.y  ),
-            Direction::SE => HexPoint::new(self.x+1, self.y-1),
-            Direction::S  => HexPoint::new(self.x,   self.y-1),
-            Direction::SW => HexPoint::new(self.x-1, self.y  ),
-            Direction::NW => HexPoint::new(self.x-1, self.y+1)
-        }
-    }
-
-    pub fn manhattan_distance(&self, other: &HexPoint) -> usize {
-        let cube_distance: isize = (self.x-other.x).abs() + 
-                                   (self.y-other.y).abs() +
-                                   (self.z-other.z).abs();
-        (cube_distance as usize)/2
-    }
-}
-
-impl fmt::Display for HexPoint {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "<HexPoint ({}, {}, {})>", self.x, self.y, self.z)
-    }
-}
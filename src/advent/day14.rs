@@ -1,9 +1,9 @@
 use advent::AdventSolver;
 use advent::knot::knot_hash;
-use advent::union_find::UnionFind;
 use failure::Error;
-use std::cmp::{max,min};
-use std::collections::BTreeSet;
+use util::components::{label,Connectivity};
+use util::grid::IPos;
+use util::render::{self,Rgb};
 
 const KEY: &str = "hfdlxzhv";
 
@@ -42,72 +42,52 @@ impl Solver {
     }
 
     fn count_regions(&self) -> usize {
-        // Pass 1: Naive labeling and build label equivalency set
-        let mut equivalents = UnionFind::new(10000);
-        let mut max_label: usize = 0;
-        let mut regions: Vec<Vec<usize>> = Vec::new();
-        for row in 0..128 {
-            let mut region_row: Vec<usize> = Vec::new();
-            for col in 0..128 {
-                if self.grid[row][col] {
-                    let mut new_label: usize = 0;
-                    let mut left_neighbor: usize = 0;
-                    let mut above_neighbor: usize = 0;
-                    if col > 0 && self.grid[row][col-1] {
-                        left_neighbor = region_row[col-1];
-                        new_label = left_neighbor;
-                    }
-                    if row > 0 && self.grid[row-1][col] {
-                        above_neighbor = regions[row-1][col];
-                        new_label = above_neighbor;
-                    }
-                    if left_neighbor > 0 && above_neighbor > 0 {
-                        let min_neighbor = min(left_neighbor, above_neighbor);
-                        let max_neighbor = max(left_neighbor, above_neighbor);
-                        if min_neighbor != max_neighbor {
-                            equivalents.unite(max_neighbor, min_neighbor);
-                        }
-                        new_label = min_neighbor;
-                    }
-                    if new_label == 0 {
-                        max_label += 1;
-                        new_label = max_label;
-                    }
-                    region_row.push(new_label);
-                } else {
-                    region_row.push(0);
-                }
-            }
-            regions.push(region_row);
-        }
-
-        // Pass 2: Use smallest equivalent label across grid (also track
-        // distinct regions for the final result)
-        let mut distinct_regions: BTreeSet<usize> = BTreeSet::new();
-        for row in 0..128 {
-            for col in 0..128 {
-                let value: usize = regions[row][col];
-                if value > 0 {
-                    distinct_regions.insert(equivalents.rep(value));
-                    regions[row][col] = equivalents.rep(value);
-                }
-            }
-        }
-
-        distinct_regions.len()
+        label(self.window(), Connectivity::Four, |pos| self.used(pos))
+            .components
+            .len()
     }
 
     #[allow(dead_code)]
     fn dump_grid(&self) {
-        for row in &self.grid {
-            for &b in row {
-                if b {
-                    print!("#");
-                } else {
-                    print!(".");
-                }
-            }
-            println!("");
-        }
+        print!("{}", render::ascii(self.window(), &[], |pos| {
+            if self.used(pos) { '#' } else { '.' }
+        }));
+    }
+
+    #[allow(dead_code)]
+    fn save_grid(&self, path: &str) -> Result<(), Error> {
+        render::save_image(path, self.window(), &[], 4, |pos| {
+            if self.used(pos) { Rgb::BLACK } else { Rgb::WHITE }
+        })
+    }
+
+    fn window(&self) -> (IPos, IPos) {
+        (IPos::origin(),
+         IPos::new(self.grid.len() as isize - 1,
+                   self.grid[0].len() as isize - 1))
+    }
+
+    fn used(&self, pos: IPos) -> bool {
+        self.grid[pos.row as usize][pos.col as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Solver,KEY};
+    use std::env;
+
+    // Not run by default: cargo test -- --ignored --nocapture
+    //
+    // Prints the disk and writes it as an image into the temp directory.
+    #[test]
+    #[ignore]
+    fn render_disk() {
+        let mut solver = Solver::default();
+        solver.build_grid(KEY);
+        solver.dump_grid();
+        let path = env::temp_dir().join("day14.pbm");
+        solver.save_grid(path.to_str().unwrap()).unwrap();
+        println!("Disk map written to {}", path.display());
     }
 }
@@ -1,35 +1,39 @@
 use advent::AdventSolver;
 use failure::Error;
-use util::duet::{self,Program};
+use util::duet::{self,Instruction,Policy,Program,Scheduler,Sound};
 
 #[derive(Default)]
 pub struct Solver;
 
 impl AdventSolver for Solver {
-    // Part 1 solution is uh, gone now. This solves part 2 only.
     fn solve(&mut self) -> Result<(), Error> {
         let instructions = duet::parse_instructions("input/day18.txt")?;
-        let mut program0 = Program::new(0, &instructions);
-        let mut program1 = Program::new(1, &instructions);
-        let mut values_sent_by_program1: usize = 0;
+        Self::run_part_1(&instructions)?;
+        Self::run_part_2(&instructions)?;
+        Ok(())
+    }
+}
+
+impl Solver {
+    // Run with sound semantics until the first frequency is recovered.
+    fn run_part_1(instructions: &Vec<Instruction>) -> Result<(), Error> {
+        let mut program = Program::with_io(0, instructions, Sound::default());
 
-        while program0.is_running() || program1.is_running() {
-            match program0.step()? {
-                Some(v) => {
-                    program1.add_to_queue(v);
-                },
-                None => {}
-            }
-            match program1.step()? {
-                Some(v) => {
-                    values_sent_by_program1 += 1;
-                    program0.add_to_queue(v);
-                },
-                None => {}
-            }
+        while program.is_running() && program.io().recovered().is_none() {
+            program.step()?;
         }
-        println!("Program1 sent {} values to program0.",
-                 values_sent_by_program1);
+        match program.io().recovered() {
+            Some(v) => println!("First recovered frequency: {}", v),
+            None => println!("Program terminated without recovering a sound.")
+        }
+        Ok(())
+    }
+
+    // Run two copies with message queue semantics, talking to each other.
+    fn run_part_2(instructions: &Vec<Instruction>) -> Result<(), Error> {
+        let mut scheduler = Scheduler::pair(instructions, Policy::RoundRobin);
+        let report = scheduler.run()?;
+        println!("Program1 sent {} values to program0.", report.stats[1].sent);
         Ok(())
     }
 }
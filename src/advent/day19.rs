@@ -1,20 +1,13 @@
 use advent::AdventSolver;
-use util::grid::Dir;
+use util::grid::{Dir,IPos};
+use util::pathfind::cell;
 use failure::Error;
 use std::fs::File;
 use std::io::{BufRead,BufReader};
 
 #[derive(Default)]
 pub struct Solver {
-    circuit: Vec<Vec<char>>,
-    width: usize,
-    height: usize
-}
-
-#[derive(Clone,Copy,Debug)]
-struct Pos {
-    row: usize,
-    col: usize
+    circuit: Vec<Vec<char>>
 }
 
 impl AdventSolver for Solver {
@@ -35,76 +28,47 @@ impl Solver {
             let line = line?;
             self.circuit.push(line.chars().collect::<Vec<char>>());
         }
-        self.height = self.circuit.len();
-        self.width = self.circuit[0].len();
         Ok(())
     }
 
-    fn find_start(&self) -> Pos {
-        Pos {
-            row: 0,
-            col: self.circuit[0].iter()
-                                .enumerate()
-                                .find(|&(_, &c)| c == '|')
-                                .unwrap().0
-        }
-    }
-
-    fn value_at(&self, pos: Pos) -> char {
-        self.circuit[pos.row][pos.col]
+    fn find_start(&self) -> IPos {
+        ipos!(0, self.circuit[0].iter()
+                                .position(|&c| c == '|')
+                                .unwrap() as isize)
     }
 
-    // Naive: what is the postion adjacent to `pos` in direction `dir`.
-    // Does not consider turning. Returns Some(pos) if neighbor is inside
-    // the circuit bounds, or None if it would go out of bounds.
-    fn neighbor(&self, pos: Pos, dir: Dir) -> Option<Pos> {
-        match dir {
-            Dir::Up    if pos.row > 0 => {
-                Some(Pos { row: pos.row-1, col: pos.col })
-            },
-            Dir::Right if pos.col < self.width-1 => {
-                Some(Pos { row: pos.row, col: pos.col+1 })
-            },
-            Dir::Down  if pos.row < self.height-1 => {
-                Some(Pos { row: pos.row+1, col: pos.col })
-            },
-            Dir::Left  if pos.col > 0 => {
-                Some(Pos { row: pos.row, col: pos.col-1 })
-            },
-            _ => { None }
-        }
+    // Outside the circuit counts as empty space.
+    fn value_at(&self, pos: IPos) -> char {
+        cell(&self.circuit, pos).cloned().unwrap_or(' ')
     }
 
     // Determine the next position along the circuit trace, which might
-    // involve changing directions. As with neighbor, returns Some(pos, dir)
-    // if the next position is in bounds, otherwise None.
-    fn next_pos(&self, pos: Pos, dir: Dir) -> Option<(Pos, Dir)> {
+    // involve changing directions. Returns Some(pos, dir) unless the trace
+    // ends here.
+    fn next_pos(&self, pos: IPos, dir: Dir) -> Option<(IPos, Dir)> {
         let mut dir = dir;
         if self.value_at(pos) == '+' {
             let right = dir.turn_right();
-            if let Some(n) = self.neighbor(pos, right) {
-                if self.value_at(n) != ' ' {
-                    dir = right
-                }
+            if self.value_at(pos.neighbor(right)) != ' ' {
+                dir = right
             }
             let left = dir.turn_left();
-            if let Some(n) = self.neighbor(pos, left) {
-                if self.value_at(n) != ' ' {
-                    dir = left;
-                }
+            if self.value_at(pos.neighbor(left)) != ' ' {
+                dir = left;
             }
         }
         // The next position is ' ' when we have reached the end of the trace.
-        if self.value_at(self.neighbor(pos, dir).unwrap()) == ' ' {
+        let next = pos.neighbor(dir);
+        if self.value_at(next) == ' ' {
             None
         } else {
-            Some((self.neighbor(pos, dir).unwrap(), dir))
+            Some((next, dir))
         }
     }
 
     // Trace the path of the circuit starting at position `pos` and heading
     // in direction `dir`.
-    fn trace_circuit(&self, pos: Pos, dir: Dir) -> Vec<char> {
+    fn trace_circuit(&self, pos: IPos, dir: Dir) -> Vec<char> {
         let mut result: Vec<char> = Vec::new();
         let mut pos = pos;
         let mut dir = dir;
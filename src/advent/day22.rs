@@ -2,7 +2,7 @@ use advent::AdventSolver;
 use failure::Error;
 use std::fs::File;
 use std::io::{BufRead,BufReader};
-use util::grid::{Dir,IPos};
+use util::grid::{Dir,Grid,IPos};
 use util::infinite_grid::InfiniteGrid;
 
 #[derive(Default)]
@@ -18,7 +18,7 @@ enum NodeState {
 
 impl AdventSolver for Solver {
     fn solve(&mut self) -> Result<(), Error> {
-        let grid = Self::read_input()?;
+        let grid = Self::read_input(InfiniteGrid::new(NodeState::Clean))?;
         Self::run_part_1(grid.clone());
         Self::run_part_2(grid.clone());
         Ok(())
@@ -26,7 +26,8 @@ impl AdventSolver for Solver {
 }
 
 impl Solver {
-    fn read_input() -> Result<InfiniteGrid<NodeState>, Error> {
+    // Load the puzzle input into an empty grid, whichever kind it is.
+    fn read_input<G: Grid<NodeState>>(mut grid: G) -> Result<G, Error> {
         let f = BufReader::new(File::open("input/day22.txt")?);
         let lines = f.lines()
                      .map(|line| line.unwrap())
@@ -36,7 +37,6 @@ impl Solver {
         let origin_row_offset = 0 - ((height / 2) as isize);
         let origin_col_offset = 0 - ((width / 2) as isize);
 
-        let mut grid = InfiniteGrid::new(NodeState::Clean);
         for row in 0..width {
             for (col, c) in lines[row].chars().enumerate() {
                 let real_row: isize = row as isize + origin_row_offset;
@@ -53,10 +53,19 @@ impl Solver {
     }
 
     fn run_part_1(mut grid: InfiniteGrid<NodeState>) {
+        let (infections_caused, _) = Self::infect(&mut grid, 10000);
+        println!("Infections caused after 10,000 iterations: {}",
+                 infections_caused);
+    }
+
+    // Run the original virus for `bursts` bursts, returning how many
+    // infections it caused and where the carrier ended up.
+    fn infect(grid: &mut InfiniteGrid<NodeState>, bursts: usize)
+            -> (usize, IPos) {
         let mut pos = IPos::origin();
         let mut dir = Dir::Up;
         let mut infections_caused: usize = 0;
-        for _ in 0..10000 {
+        for _ in 0..bursts {
             match grid[pos] {
                 NodeState::Clean    => {
                     dir = dir.turn_left();
@@ -71,15 +80,17 @@ impl Solver {
             }
             pos = pos.neighbor(dir);
         }
-        println!("Infections caused after 10,000 iterations: {}",
-                 infections_caused);
+        (infections_caused, pos)
     }
 
-    fn run_part_2(mut grid: InfiniteGrid<NodeState>) {
+    // Run the evolved virus for `bursts` bursts, returning how many
+    // infections it caused and where the carrier ended up.
+    fn evolve<G: Grid<NodeState>>(grid: &mut G, bursts: usize)
+            -> (usize, IPos) {
         let mut pos = ipos!(0, 0);
         let mut dir = Dir::Up;
         let mut infections_caused: usize = 0;
-        for _ in 0..10_000_000 {
+        for _ in 0..bursts {
             match grid[pos] {
                 NodeState::Clean    => {
                     dir = dir.turn_left();
@@ -100,7 +111,90 @@ impl Solver {
             }
             pos = pos.neighbor(dir);
         }
+        (infections_caused, pos)
+    }
+
+    fn run_part_2(mut grid: InfiniteGrid<NodeState>) {
+        let (infections_caused, _) = Self::evolve(&mut grid, 10_000_000);
         println!("Infections caused after 10,000 iterations: {}",
                  infections_caused);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{NodeState,Solver};
+    use std::env;
+    use std::fs::File;
+    use std::io::Write;
+    use std::time::Instant;
+    use util::dense_grid::DenseGrid;
+    use util::grid::{Grid,IPos};
+    use util::infinite_grid::InfiniteGrid;
+    use util::render::{Highlight,Render,Rgb};
+    use util::sparse_grid::SparseGrid;
+
+    fn carrier(pos: IPos) -> Highlight {
+        Highlight {
+            pos: pos,
+            ch: '@',
+            color: Rgb(255, 0, 0)
+        }
+    }
+
+    // Not run by default: cargo test --release -- --ignored --nocapture
+    //
+    // Draws the map after each part, with the carrier in red, into the
+    // temp directory.
+    #[test]
+    #[ignore]
+    fn render_maps() {
+        let grid = Solver::read_input(InfiniteGrid::new(NodeState::Clean))
+                          .unwrap();
+
+        let mut part_1 = grid.clone();
+        let (_, pos) = Solver::infect(&mut part_1, 10000);
+        let path = env::temp_dir().join("day22.txt");
+        let text = part_1.to_ascii(None, &[carrier(pos)], |node| match *node {
+            NodeState::Infected => '#',
+            _                   => '.'
+        });
+        File::create(&path).unwrap().write_all(text.as_bytes()).unwrap();
+        println!("Part 1 map written to {}", path.display());
+
+        let mut part_2 = grid.clone();
+        let (_, pos) = Solver::evolve(&mut part_2, 10_000_000);
+        let path = env::temp_dir().join("day22.ppm");
+        part_2.save_image(path.to_str().unwrap(), None, &[carrier(pos)], 2,
+                          |node| match *node {
+            NodeState::Clean    => Rgb::WHITE,
+            NodeState::Infected => Rgb::BLACK,
+            NodeState::Weakened => Rgb(160, 160, 255),
+            NodeState::Flagged  => Rgb(255, 200, 0),
+        }).unwrap();
+        println!("Part 2 map written to {}", path.display());
+    }
+
+    fn time_part_2<G: Grid<NodeState>>(name: &str, grid: G) {
+        let mut grid = Solver::read_input(grid).unwrap();
+        let start = Instant::now();
+        let (infections, _) = Solver::evolve(&mut grid, 10_000_000);
+        let elapsed = start.elapsed();
+        let seconds = elapsed.as_secs() as f64 +
+                      elapsed.subsec_nanos() as f64 / 1e9;
+        assert_eq!(infections, 2511090);
+        println!("{:>8}: {:.2}s", name, seconds);
+    }
+
+    // Not run by default: cargo test --release -- --ignored --nocapture
+    //
+    // Here the dense grid took 2.2s, the sparse one 1.3s and the tiled
+    // InfiniteGrid 0.3s (0.9s before it cached the last tile used).
+    #[test]
+    #[ignore]
+    fn bench_part_2() {
+        time_part_2("dense", DenseGrid::new(NodeState::Clean));
+        time_part_2("sparse", SparseGrid::new(NodeState::Clean));
+        time_part_2("tiled", InfiniteGrid::new(NodeState::Clean));
+    }
+}
@@ -1,6 +1,7 @@
 use advent::AdventSolver;
 use failure::Error;
-use util::duet::{self,Instruction,Program};
+use std::u64;
+use util::duet::{self,Backend,Instruction,Program};
 
 #[derive(Default)]
 pub struct Solver;
@@ -13,8 +14,11 @@ impl AdventSolver for Solver {
         // between 105700 and 122700 (with step 17, so 1001 iterations).
         // Second version is hand-optimized by me to use `mod` instruction when
         // testing primality, instead of the O(n^2) inner loop in the original.
+        // The compiled backend can run the original as-is: it works out the
+        // inner loop's result instead of iterating it.
+        Self::run_part_2(&instructions, Backend::Compiled);
         let instructions = duet::parse_instructions("input/day23_opt.txt")?;
-        Self::run_part_2(&instructions);
+        Self::run_part_2(&instructions, Backend::Interpreter);
         Ok(())
     }
 }
@@ -41,18 +45,13 @@ impl Solver {
         println!("{} multiply calls.", multiply_calls);
     }
 
-    fn run_part_2(instructions: &Vec<Instruction>) {
+    fn run_part_2(instructions: &Vec<Instruction>, backend: Backend) {
         let mut program = Program::new(0, &instructions);
+        program.set_backend(backend);
         program.store('a', 1);
 
-        while program.is_running() {
-            match program.step() {
-                Ok(_) => {},
-                Err(e) => {
-                    println!("Program exited with error: {}", e);
-                    break;
-                }
-            }
+        if let Err(e) = program.run(u64::MAX) {
+            println!("Program exited with error: {}", e);
         }
 
         println!("Program terminated with register h: {}",
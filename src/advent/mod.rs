@@ -1,7 +1,6 @@
 use failure::Error;
 
 mod knot;
-mod union_find;
 
 trait AdventSolver {
     fn solve(&mut self) -> Result<(), Error>;
@@ -12,25 +12,206 @@ extern crate regex;
 mod util;
 mod advent;
 
-use argparse::{ArgumentParser, StoreOption};
+use argparse::{ArgumentParser, StoreOption, StoreTrue};
+use failure::Error;
+use std::fs::File;
+use std::io::{self,BufWriter,Read};
+use util::conditional;
+use util::duet::{asm,trace};
+use util::duet::analyze::{self,Interval};
 
 fn main() {
     let mut day: Option<usize> = None;
+    let mut record: Option<String> = None;
+    let mut replay: Option<String> = None;
+    let mut output: Option<String> = None;
+    let mut diff: Option<String> = None;
+    let mut program: Option<String> = None;
+    let mut steps: Option<u64> = None;
+    let mut disassemble: Option<String> = None;
+    let mut analyze: Option<String> = None;
+    let mut initial: Option<String> = None;
+    let mut arithmetic: Option<String> = None;
+    let mut repl = false;
+    let mut history: Option<String> = None;
     {
         let mut parser = ArgumentParser::new();
         parser.set_description("Advent of Code 2017");
         parser.refer(&mut day)
               .add_option(&["-d", "--day"], StoreOption,
                           "number of challenge to run");
+        parser.refer(&mut record)
+              .add_option(&["--record"], StoreOption,
+                          "duet program to run, recording a trace");
+        parser.refer(&mut output)
+              .add_option(&["--output"], StoreOption,
+                          "file written by --record or --history");
+        parser.refer(&mut steps)
+              .add_option(&["--steps"], StoreOption,
+                          "step budget for --record; running out is an \
+                           error");
+        parser.refer(&mut replay)
+              .add_option(&["--replay"], StoreOption,
+                          "duet trace file to step through");
+        parser.refer(&mut diff)
+              .add_option(&["--diff"], StoreOption,
+                          "second trace to compare against --replay");
+        parser.refer(&mut program)
+              .add_option(&["--program"], StoreOption,
+                          "duet program the --replay trace was recorded \
+                           from, for its register names");
+        parser.refer(&mut disassemble)
+              .add_option(&["--disassemble"], StoreOption,
+                          "duet program to print in canonical form");
+        parser.refer(&mut analyze)
+              .add_option(&["--analyze"], StoreOption,
+                          "duet program to print register ranges for");
+        parser.refer(&mut initial)
+              .add_option(&["--initial"], StoreOption,
+                          "starting registers for --record or --analyze, \
+                           e.g. a=1 (--analyze also takes ranges: p=0..1)");
+        parser.refer(&mut arithmetic)
+              .add_option(&["--arithmetic"], StoreOption,
+                          "what --record and --analyze do on overflow: \
+                           checked (the default), wrapping or saturating");
+        parser.refer(&mut history)
+              .add_option(&["--history"], StoreOption,
+                          "day 8 style program to run, writing register \
+                           values after every step as CSV");
+        parser.refer(&mut repl)
+              .add_option(&["--repl"], StoreTrue,
+                          "interpreter for day 8's register language");
         parser.parse_args_or_exit();
     }
-    match day {
-        Some(ref day) => {
-            match advent::solve(*day) {
-                Ok(_) => {},
-                Err(e) => println!("error: {}", e)
+    let initial = initial.as_ref().map(|s| s.as_str());
+    let arithmetic = arithmetic.as_ref().map(|s| s.as_str());
+    let result: Result<(), Error> =
+        if let Some(ref program) = record {
+            let output = output.unwrap_or("trace.txt".to_string());
+            record_trace(program, &output, initial, arithmetic, steps)
+        } else if let Some(ref program) = disassemble {
+            pretty_print(program)
+        } else if let Some(ref program) = analyze {
+            print_ranges(program, initial, arithmetic)
+        } else if let Some(ref program) = history {
+            let output = output.unwrap_or("history.csv".to_string());
+            record_history(program, &output)
+        } else if repl {
+            let stdin = io::stdin();
+            conditional::repl(stdin.lock(), io::stdout(), true)
+        } else if let Some(ref path) = replay {
+            trace::browse(path, diff.as_ref().map(|s| s.as_str()),
+                          program.as_ref().map(|s| s.as_str()))
+        } else {
+            match day {
+                Some(ref day) => advent::solve(*day),
+                None => {
+                    println!("--day is required");
+                    Ok(())
+                }
             }
-        },
-        None => println!("--day is required"),
+        };
+    match result {
+        Ok(_) => {},
+        Err(e) => println!("error: {}", e)
     }
 }
+
+// Print a duet program as the disassembler sees it, with labels.
+fn pretty_print(file: &str) -> Result<(), Error> {
+    let mut source = String::new();
+    File::open(file)?.read_to_string(&mut source)?;
+    let assembly = asm::assemble(&source)?;
+    print!("{}", assembly.disassemble(true));
+    Ok(())
+}
+
+// Parse --initial: comma-separated register=value or register=low..high
+// settings, e.g. "a=1,p=0..1".
+fn parse_initial(initial: Option<&str>)
+        -> Result<Vec<(String, Interval)>, Error> {
+    let mut registers = Vec::new();
+    for setting in initial.unwrap_or("").split(',').filter(|s| !s.is_empty()) {
+        let parts: Vec<&str> = setting.splitn(2, '=').collect();
+        if parts.len() != 2 {
+            return Err(format_err!("expected register=value: {}", setting));
+        }
+        let bounds: Vec<&str> = parts[1].splitn(2, "..").collect();
+        let low: isize = bounds[0].trim().parse()?;
+        let high: isize = match bounds.get(1) {
+            Some(high) => high.trim().parse()?,
+            None => low
+        };
+        registers.push((parts[0].trim().to_string(), Interval::new(low, high)));
+    }
+    Ok(registers)
+}
+
+// Run a duet program, recording a trace. Unlike --analyze, every register in
+// --initial needs a single starting value.
+fn record_trace(file: &str, output: &str, initial: Option<&str>,
+                arithmetic: Option<&str>, steps: Option<u64>)
+        -> Result<(), Error> {
+    let mut registers = Vec::new();
+    for (name, range) in parse_initial(initial)? {
+        match range.value() {
+            Some(value) => registers.push((name, value)),
+            None => return Err(format_err!("--record needs a single value \
+                                            for {}, not {}", name, range))
+        }
+    }
+    trace::record(file, output, &registers,
+                  arithmetic.unwrap_or("checked").parse()?, steps)
+}
+
+// Print a duet program with the registers' ranges before each instruction,
+// followed by anything that might go wrong at runtime.
+fn print_ranges(file: &str, initial: Option<&str>, arithmetic: Option<&str>)
+        -> Result<(), Error> {
+    let mut source = String::new();
+    File::open(file)?.read_to_string(&mut source)?;
+    let assembly = asm::assemble(&source)?;
+    let name = |r: char| {
+        assembly.registers
+                .iter()
+                .find(|&(_, &c)| c == r)
+                .map_or(r.to_string(), |(name, _)| name.clone())
+    };
+    let mut registers = Vec::new();
+    for (name, range) in parse_initial(initial)? {
+        registers.push((assembly.register(&name)?, range));
+    }
+    let analysis = analyze::analyze(&assembly.instructions, &registers,
+                                    arithmetic.unwrap_or("checked").parse()?);
+    let listing = assembly.disassemble(false);
+    for (pc, line) in listing.lines().enumerate() {
+        let facts = if analysis.reachable(pc) {
+            analysis.facts(pc)
+                    .iter()
+                    .map(|&(r, range)| format!("{}={}", name(r), range))
+                    .collect::<Vec<String>>()
+                    .join(" ")
+        } else {
+            "unreachable".to_string()
+        };
+        println!("{:>4}  {:<16}  {}", pc, line, facts);
+    }
+    for warning in &analysis.warnings {
+        println!("warning: {}", warning);
+    }
+    Ok(())
+}
+
+// Run a program in the day 8 language, writing its register history to
+// `output` and printing a summary.
+fn record_history(file: &str, output: &str) -> Result<(), Error> {
+    let mut machine = conditional::Machine::new(
+        conditional::load_program(file)?);
+    machine.record_history();
+    machine.run()?;
+    let history = machine.history().unwrap();
+    history.write_csv(BufWriter::new(File::create(output)?))?;
+    println!("{} steps written to {}", history.len(), output);
+    print!("{}", history.summary());
+    Ok(())
+}
@@ -0,0 +1,171 @@
+use std::collections::VecDeque;
+use util::grid::{extend_bounds,IPos,Neighbors};
+
+// Connected-component labeling: splitting the cells of a grid that satisfy
+// a predicate into regions of cells that touch.
+
+// Which cells touch: those sharing an edge, or corners too.
+#[allow(dead_code)]
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Connectivity {
+    Four,
+    Eight
+}
+
+impl Connectivity {
+    fn neighbors(&self, pos: IPos) -> Neighbors {
+        match *self {
+            Connectivity::Four => pos.neighbors4(),
+            Connectivity::Eight => pos.neighbors8()
+        }
+    }
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub struct Component {
+    // How many cells it has.
+    pub size: usize,
+    // Its top left and bottom right corners.
+    pub bounds: (IPos, IPos)
+}
+
+// The components found within a window, numbered from 0 in the order their
+// first cells come reading row by row.
+#[derive(Clone,Debug)]
+pub struct Labels {
+    window: (IPos, IPos),
+    width: usize,
+    // The label of each cell in the window, row by row.
+    labels: Vec<Option<usize>>,
+    pub components: Vec<Component>
+}
+
+impl Labels {
+    // The component `pos` belongs to, if any.
+    pub fn label(&self, pos: IPos) -> Option<usize> {
+        self.index(pos).and_then(|i| self.labels[i])
+    }
+
+    // Each row of the window's labels, from the top.
+    #[allow(dead_code)]
+    pub fn rows(&self) -> Vec<&[Option<usize>]> {
+        if self.width == 0 {
+            return Vec::new();
+        }
+        self.labels.chunks(self.width).collect()
+    }
+
+    fn index(&self, pos: IPos) -> Option<usize> {
+        let (min, max) = self.window;
+        if pos.row < min.row || pos.row > max.row ||
+           pos.col < min.col || pos.col > max.col {
+            return None;
+        }
+        Some((pos.row - min.row) as usize * self.width +
+             (pos.col - min.col) as usize)
+    }
+}
+
+// Label the connected components of the cells within `window` (top left and
+// bottom right corners, inclusive) that satisfy `predicate`.
+pub fn label<P>(window: (IPos, IPos), connectivity: Connectivity,
+                predicate: P) -> Labels
+        where P: Fn(IPos) -> bool {
+    let (min, max) = window;
+    let height = if max.row < min.row { 0 } else { max.row - min.row + 1 };
+    let width = if max.col < min.col { 0 } else { max.col - min.col + 1 };
+    let mut labels = Labels {
+        window: window,
+        width: width as usize,
+        labels: vec![None; (height * width) as usize],
+        components: Vec::new()
+    };
+    let mut queue: VecDeque<IPos> = VecDeque::new();
+    for row in min.row..min.row + height {
+        for col in min.col..min.col + width {
+            let start = ipos!(row, col);
+            if labels.label(start).is_some() || !predicate(start) {
+                continue;
+            }
+            let id = labels.components.len();
+            let mut size = 0;
+            let mut bounds = None;
+            let i = labels.index(start).unwrap();
+            labels.labels[i] = Some(id);
+            queue.push_back(start);
+            while let Some(pos) = queue.pop_front() {
+                size += 1;
+                bounds = extend_bounds(bounds, pos);
+                for next in connectivity.neighbors(pos) {
+                    match labels.index(next) {
+                        Some(i) if labels.labels[i].is_none() &&
+                                   predicate(next) => {
+                            labels.labels[i] = Some(id);
+                            queue.push_back(next);
+                        },
+                        _ => {}
+                    }
+                }
+            }
+            labels.components.push(Component {
+                size: size,
+                bounds: bounds.unwrap()
+            });
+        }
+    }
+    labels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{label,Connectivity};
+    use util::grid::IPos;
+
+    const PICTURE: [&str; 4] = ["##..#",
+                                "#..#.",
+                                "..##.",
+                                "#...."];
+
+    fn filled(pos: IPos) -> bool {
+        PICTURE[pos.row as usize].as_bytes()[pos.col as usize] == b'#'
+    }
+
+    fn window() -> (IPos, IPos) {
+        (ipos!(0, 0), ipos!(3, 4))
+    }
+
+    #[test]
+    fn four_connected() {
+        let labels = label(window(), Connectivity::Four, filled);
+        let sizes: Vec<usize> = labels.components
+                                      .iter()
+                                      .map(|c| c.size)
+                                      .collect();
+        assert_eq!(sizes, vec![3, 1, 3, 1]);
+        assert_eq!(labels.components[2].bounds, (ipos!(1, 2), ipos!(2, 3)));
+        assert_eq!(labels.label(ipos!(2, 2)), Some(2));
+        assert_eq!(labels.label(ipos!(1, 3)), Some(2));
+        assert_eq!(labels.label(ipos!(0, 2)), None);
+        assert_eq!(labels.label(ipos!(9, 9)), None);
+        assert_eq!(labels.rows()[3], &[Some(3), None, None, None, None][..]);
+    }
+
+    #[test]
+    fn eight_connected() {
+        let labels = label(window(), Connectivity::Eight, filled);
+        let sizes: Vec<usize> = labels.components
+                                      .iter()
+                                      .map(|c| c.size)
+                                      .collect();
+        assert_eq!(sizes, vec![3, 4, 1]);
+        assert_eq!(labels.components[1].bounds, (ipos!(0, 2), ipos!(2, 4)));
+    }
+
+    #[test]
+    fn empty_window() {
+        let labels = label((ipos!(0, 0), ipos!(-1, -1)), Connectivity::Four,
+                           |_| true);
+        assert!(labels.components.is_empty());
+        assert!(labels.rows().is_empty());
+    }
+}
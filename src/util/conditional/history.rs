@@ -0,0 +1,224 @@
+use failure::Error;
+use std::collections::{BTreeMap,BTreeSet};
+use std::io::Write;
+
+// A register write: after the instruction at `step`, `register` held `value`.
+#[derive(Clone,Debug,PartialEq)]
+pub struct Change {
+    pub step: usize,
+    pub register: String,
+    pub value: isize
+}
+
+// The lowest and highest values a register held, and the steps whose
+// instructions first produced them. A step of None means the register's
+// initial zero was never beaten.
+#[derive(Clone,Debug,PartialEq)]
+pub struct Extremes {
+    pub register: String,
+    pub last: isize,
+    pub min: isize,
+    pub min_step: Option<usize>,
+    pub max: isize,
+    pub max_step: Option<usize>
+}
+
+// Every instruction a Machine ran, in order, and every register write.
+#[derive(Clone,Debug,Default)]
+pub struct History {
+    instructions: Vec<String>,
+    changes: Vec<Change>
+}
+
+impl History {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    // Note that `instruction` ran, and what it wrote if its condition held.
+    pub fn record(&mut self, instruction: String,
+                  change: Option<(&str, isize)>) {
+        let step = self.instructions.len();
+        self.instructions.push(instruction);
+        if let Some((register, value)) = change {
+            self.changes.push(Change {
+                step: step,
+                register: register.to_string(),
+                value: value
+            });
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.instructions.len()
+    }
+
+    // The instruction run at `step`.
+    #[allow(dead_code)]
+    pub fn instruction(&self, step: usize) -> &str {
+        &self.instructions[step]
+    }
+
+    #[allow(dead_code)]
+    pub fn changes(&self) -> &[Change] {
+        &self.changes
+    }
+
+    // Every register written to, sorted by name.
+    pub fn registers(&self) -> Vec<&str> {
+        self.changes
+            .iter()
+            .map(|c| &c.register[..])
+            .collect::<BTreeSet<&str>>()
+            .into_iter()
+            .collect()
+    }
+
+    // The range each register covered, sorted by register name.
+    pub fn extremes(&self) -> Vec<Extremes> {
+        let mut extremes: BTreeMap<&str, Extremes> = BTreeMap::new();
+        for change in &self.changes {
+            let e = extremes.entry(&change.register).or_insert(Extremes {
+                register: change.register.clone(),
+                last: 0,
+                min: 0,
+                min_step: None,
+                max: 0,
+                max_step: None
+            });
+            e.last = change.value;
+            if change.value < e.min {
+                e.min = change.value;
+                e.min_step = Some(change.step);
+            }
+            if change.value > e.max {
+                e.max = change.value;
+                e.max_step = Some(change.step);
+            }
+        }
+        extremes.into_iter().map(|(_, e)| e).collect()
+    }
+
+    // Write the history as CSV: a header, a row of zeros for the starting
+    // state, then one row per step with every register's value after it.
+    //
+    //   step,instruction,a,b
+    //   ,,0,0
+    //   0,b inc 5 if a > 1,0,0
+    //   1,a inc 1 if b < 5,1,0
+    pub fn write_csv<W: Write>(&self, mut writer: W) -> Result<(), Error> {
+        let registers = self.registers();
+        let mut values = vec![0; registers.len()];
+        writeln!(writer, "step,instruction,{}", registers.join(","))?;
+        writeln!(writer, ",,{}", join(&values))?;
+        let mut changes = self.changes.iter().peekable();
+        for (step, instruction) in self.instructions.iter().enumerate() {
+            while let Some(change) = changes.peek()
+                                            .cloned()
+                                            .filter(|c| c.step == step) {
+                let i = registers.binary_search(&&change.register[..])
+                                 .unwrap();
+                values[i] = change.value;
+                changes.next();
+            }
+            writeln!(writer, "{},{},{}", step, instruction, join(&values))?;
+        }
+        Ok(())
+    }
+
+    // A table of every register's final value, lowest and highest value,
+    // and the instructions that caused those, largest final value first.
+    pub fn summary(&self) -> String {
+        let mut extremes = self.extremes();
+        extremes.sort_by(|a, b| b.last.cmp(&a.last)
+                                      .then(a.register.cmp(&b.register)));
+        let cause = |step: Option<usize>| match step {
+            Some(step) => format!("{}: {}", step, self.instructions[step]),
+            None => "start".to_string()
+        };
+        let rows: Vec<[String; 6]> = extremes.iter().map(|e| {
+            [e.register.clone(), e.last.to_string(),
+             e.min.to_string(), cause(e.min_step),
+             e.max.to_string(), cause(e.max_step)]
+        }).collect();
+        let header = ["register", "final", "min", "min at", "max", "max at"];
+        let mut widths: Vec<usize> = header.iter().map(|h| h.len()).collect();
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row.iter()) {
+                *width = (*width).max(cell.len());
+            }
+        }
+        let mut table = String::new();
+        let mut line = |cells: &[&str]| {
+            // No padding after the last column.
+            let last = cells.len() - 1;
+            let padded: Vec<String> = cells.iter()
+                                           .zip(widths.iter())
+                                           .map(|(c, &w)| {
+                                               format!("{:<1$}", c, w)
+                                           })
+                                           .take(last)
+                                           .collect();
+            table.push_str(&padded.join("  "));
+            table.push_str("  ");
+            table.push_str(cells[last]);
+            table.push('\n');
+        };
+        line(&header);
+        for row in &rows {
+            line(&row.iter().map(|c| &c[..]).collect::<Vec<&str>>());
+        }
+        table
+    }
+}
+
+fn join(values: &[isize]) -> String {
+    values.iter().map(|v| v.to_string()).collect::<Vec<String>>().join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use util::conditional::{parse_program,Machine};
+
+    fn example() -> Machine {
+        let mut machine = Machine::new(parse_program("
+            b inc 5 if a > 1
+            a inc 1 if b < 5
+            c dec -10 if a >= 1
+            c inc -20 if c == 10
+        ".as_bytes()).unwrap());
+        machine.record_history();
+        machine.run().unwrap();
+        machine
+    }
+
+    #[test]
+    fn csv_has_a_row_per_step() {
+        let machine = example();
+        let mut csv = Vec::new();
+        machine.history().unwrap().write_csv(&mut csv).unwrap();
+        assert_eq!(String::from_utf8(csv).unwrap(),
+                   "step,instruction,a,c\n\
+                    ,,0,0\n\
+                    0,b inc 5 if a > 1,0,0\n\
+                    1,a inc 1 if b < 5,1,0\n\
+                    2,c dec -10 if a >= 1,1,10\n\
+                    3,c inc -20 if c == 10,1,-10\n");
+    }
+
+    #[test]
+    fn peaks_name_their_instructions() {
+        let machine = example();
+        let history = machine.history().unwrap();
+        let c = &history.extremes()[1];
+        assert_eq!((c.min, c.min_step, c.max, c.max_step),
+                   (-10, Some(3), 10, Some(2)));
+        assert_eq!(history.summary(),
+                   "register  final  min  min at                   \
+                    max  max at\n\
+                    a         1      0    start                    \
+                    1    1: a inc 1 if b < 5\n\
+                    c         -10    -10  3: c inc -20 if c == 10  \
+                    10   2: c dec -10 if a >= 1\n");
+    }
+}
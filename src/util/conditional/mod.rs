@@ -0,0 +1,452 @@
+use failure::Error;
+use regex::Regex;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead,BufReader,Read,Write};
+
+mod history;
+
+pub use self::history::History;
+
+// The conditional register language from day 8:
+//
+//   b inc 5 if a > 1
+//   a inc 1 if b < 5
+//   c dec -10 if a >= 1
+//
+// Registers have names of any length and start at zero. On top of the
+// puzzle's inc and dec, this also accepts mul and set, and any value may be
+// a register instead of a number. The condition is optional.
+
+// Only numbers may have a minus sign: -b is not a way to negate a register.
+lazy_static! {
+    static ref INSTRUCTION_RE: Regex = Regex::new(concat!(
+        r"^(\w+)\s+(\w+)\s+(-?\d+|\w+)",
+        r"(?:\s+if\s+(-?\d+|\w+)\s*([<>=!]+)\s*(-?\d+|\w+))?$"
+    )).unwrap();
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub enum Operand {
+    Register(String),
+    Value(isize)
+}
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Operation {
+    Inc,
+    Dec,
+    Mul,
+    Set
+}
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Comparator {
+    EQ,
+    LE,
+    LT,
+    GE,
+    GT,
+    NE
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub struct Condition {
+    pub left: Operand,
+    pub comp: Comparator,
+    pub right: Operand
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub struct Instruction {
+    pub reg: String,
+    pub op: Operation,
+    pub amount: Operand,
+    pub cond: Option<Condition>
+}
+
+impl Operand {
+    fn parse(text: &str) -> Operand {
+        match text.parse::<isize>() {
+            Ok(v) => Operand::Value(v),
+            Err(_) => Operand::Register(text.to_string())
+        }
+    }
+}
+
+impl Instruction {
+    pub fn parse(text: &str) -> Result<Instruction, Error> {
+        let caps = match INSTRUCTION_RE.captures(text.trim()) {
+                       Some(caps) => caps,
+                       None => return Err(format_err!(
+                                   "Couldn't parse instruction {}", text))
+                   };
+        let op = match &caps[2] {
+            "inc" => Operation::Inc,
+            "dec" => Operation::Dec,
+            "mul" => Operation::Mul,
+            "set" => Operation::Set,
+            _ => return Err(format_err!("Unrecognized instruction: {}", text))
+        };
+        let cond = match caps.get(4) {
+            Some(left) => Some(Condition {
+                left: Operand::parse(left.as_str()),
+                comp: match &caps[5] {
+                    "==" => Comparator::EQ,
+                    "<=" => Comparator::LE,
+                    "<"  => Comparator::LT,
+                    ">=" => Comparator::GE,
+                    ">"  => Comparator::GT,
+                    "!=" => Comparator::NE,
+                    _    => return Err(format_err!(
+                                "Unrecognized comparator: {}", text))
+                },
+                right: Operand::parse(&caps[6])
+            }),
+            None => None
+        };
+        Ok(Instruction {
+            reg: caps[1].to_string(),
+            op: op,
+            amount: Operand::parse(&caps[3]),
+            cond: cond
+        })
+    }
+}
+
+// Parse a program, one instruction per line. Blank lines are skipped.
+pub fn parse_program<R: Read>(reader: R) -> Result<Vec<Instruction>, Error> {
+    let mut instructions = Vec::new();
+    for line in BufReader::new(reader).lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            instructions.push(Instruction::parse(&line)?);
+        }
+    }
+    Ok(instructions)
+}
+
+pub fn load_program(file: &str) -> Result<Vec<Instruction>, Error> {
+    parse_program(File::open(file)?)
+}
+
+pub struct Machine {
+    instructions: Vec<Instruction>,
+    registers: HashMap<String, isize>,
+    // The largest value each register has held, counting its initial zero.
+    max_values: HashMap<String, isize>,
+    pc: usize,
+    history: Option<History>
+}
+
+impl Machine {
+    pub fn new(instructions: Vec<Instruction>) -> Self {
+        Machine {
+            instructions: instructions,
+            registers: HashMap::new(),
+            max_values: HashMap::new(),
+            pc: 0,
+            history: None
+        }
+    }
+
+    // Keep a History of every instruction run by step() from now on.
+    pub fn record_history(&mut self) {
+        self.history = Some(History::new());
+    }
+
+    pub fn history(&self) -> Option<&History> {
+        self.history.as_ref()
+    }
+
+    // The index of the next instruction to run.
+    #[allow(dead_code)]
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.pc >= self.instructions.len()
+    }
+
+    // Run the next instruction of the program. Returns false, doing nothing,
+    // once the program has finished.
+    pub fn step(&mut self) -> Result<bool, Error> {
+        if self.is_finished() {
+            return Ok(false);
+        }
+        let inst = self.instructions[self.pc].clone();
+        let held = self.execute(&inst)
+                       .map_err(|e| format_err!("{} (line {})",
+                                                e, self.pc + 1))?;
+        if self.history.is_some() {
+            let value = self.register(&inst.reg);
+            let change = if held { Some((&inst.reg[..], value)) } else { None };
+            self.history.as_mut().unwrap().record(inst.to_string(), change);
+        }
+        self.pc += 1;
+        Ok(true)
+    }
+
+    // Run the program to the end, returning how many steps that took.
+    pub fn run(&mut self) -> Result<usize, Error> {
+        let mut steps = 0;
+        while self.step()? {
+            steps += 1;
+        }
+        Ok(steps)
+    }
+
+    // Run one instruction, whether or not it's part of the program. Returns
+    // whether its condition held.
+    pub fn execute(&mut self, inst: &Instruction) -> Result<bool, Error> {
+        if let Some(ref cond) = inst.cond {
+            let left = self.value(&cond.left);
+            let right = self.value(&cond.right);
+            let holds = match cond.comp {
+                Comparator::EQ => left == right,
+                Comparator::LE => left <= right,
+                Comparator::LT => left <  right,
+                Comparator::GE => left >= right,
+                Comparator::GT => left >  right,
+                Comparator::NE => left != right,
+            };
+            if !holds {
+                return Ok(false);
+            }
+        }
+        let original = self.register(&inst.reg);
+        let amount = self.value(&inst.amount);
+        let new_value = match inst.op {
+            Operation::Inc => original.checked_add(amount),
+            Operation::Dec => original.checked_sub(amount),
+            Operation::Mul => original.checked_mul(amount),
+            Operation::Set => Some(amount)
+        };
+        let new_value = match new_value {
+            Some(v) => v,
+            None => return Err(format_err!("overflow in \"{}\"", inst))
+        };
+        self.registers.insert(inst.reg.clone(), new_value);
+        let max = self.max_values.entry(inst.reg.clone()).or_insert(0);
+        if new_value > *max {
+            *max = new_value;
+        }
+        Ok(true)
+    }
+
+    fn value(&self, op: &Operand) -> isize {
+        match *op {
+            Operand::Register(ref r) => self.register(r),
+            Operand::Value(v) => v
+        }
+    }
+
+    // A register's value; registers never written to are zero.
+    pub fn register(&self, name: &str) -> isize {
+        self.registers.get(name).cloned().unwrap_or(0)
+    }
+
+    // Every register written to so far, sorted by name.
+    pub fn registers(&self) -> Vec<(&str, isize)> {
+        let mut registers: Vec<(&str, isize)> =
+            self.registers.iter().map(|(r, &v)| (&r[..], v)).collect();
+        registers.sort();
+        registers
+    }
+
+    // The register with the largest value now. Ties go to the first name.
+    pub fn largest(&self) -> Option<(&str, isize)> {
+        self.registers()
+            .into_iter()
+            .fold(None, |best, (r, v)| match best {
+                Some((_, b)) if b >= v => best,
+                _ => Some((r, v))
+            })
+    }
+
+    // The largest value a register has ever held (0 if never written).
+    pub fn max_value(&self, name: &str) -> isize {
+        self.max_values.get(name).cloned().unwrap_or(0)
+    }
+
+    // The largest value any register has ever held, or 0.
+    pub fn largest_ever(&self) -> isize {
+        self.max_values.values().cloned().max().unwrap_or(0)
+    }
+}
+
+// Read instructions and commands a line at a time, running each against one
+// Machine and writing what happened. Instructions print the register they
+// change, or "skipped" if their condition didn't hold. Commands:
+//
+//   :print <r>   print a register's value
+//   :regs        print every register written so far
+//   :max         print the largest value each register has held
+//   :reset       start again with every register at zero
+//   :quit        stop reading
+//
+// Blank lines and lines starting with # are ignored. With `prompt`, a
+// prompt is written before each line is read.
+pub fn repl<R: BufRead, W: Write>(input: R, mut output: W, prompt: bool)
+        -> Result<(), Error> {
+    let mut machine = Machine::new(Vec::new());
+    if prompt {
+        write!(output, "> ")?;
+        output.flush()?;
+    }
+    for line in input.lines() {
+        let line = line?;
+        let line = line.trim();
+        let tokens = line.split_whitespace().collect::<Vec<&str>>();
+        match tokens.get(0).cloned() {
+            None => {},
+            Some(t) if t.starts_with('#') => {},
+            Some(":print") if tokens.len() == 2 => {
+                writeln!(output, "{} = {}",
+                         tokens[1], machine.register(tokens[1]))?;
+            },
+            Some(":regs") => for (r, v) in machine.registers() {
+                writeln!(output, "{} = {}", r, v)?;
+            },
+            Some(":max") => for (r, _) in machine.registers() {
+                writeln!(output, "{} <= {}", r, machine.max_value(r))?;
+            },
+            Some(":reset") => machine = Machine::new(Vec::new()),
+            Some(":quit") => break,
+            Some(t) if t.starts_with(':') => {
+                writeln!(output, "commands: :print <r>, :regs, :max, \
+                                  :reset, :quit")?;
+            },
+            Some(_) => {
+                match Instruction::parse(line)
+                          .and_then(|inst| {
+                              machine.execute(&inst).map(|done| (inst, done))
+                          }) {
+                    Ok((ref inst, true)) => {
+                        writeln!(output, "{} = {}",
+                                 inst.reg, machine.register(&inst.reg))?;
+                    },
+                    Ok((_, false)) => writeln!(output, "skipped")?,
+                    Err(e) => writeln!(output, "error: {}", e)?
+                }
+            }
+        }
+        if prompt {
+            write!(output, "> ")?;
+            output.flush()?;
+        }
+    }
+    Ok(())
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Operand::Register(ref r) => write!(f, "{}", r),
+            Operand::Value(v) => write!(f, "{}", v)
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {}", self.reg, self.op, self.amount)?;
+        if let Some(ref cond) = self.cond {
+            write!(f, " if {} {} {}", cond.left, cond.comp, cond.right)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", match *self {
+                            Operation::Inc => "inc",
+                            Operation::Dec => "dec",
+                            Operation::Mul => "mul",
+                            Operation::Set => "set",
+                        })
+    }
+}
+
+impl fmt::Display for Comparator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", match *self {
+                            Comparator::EQ => "==",
+                            Comparator::LE => "<=",
+                            Comparator::LT => "<",
+                            Comparator::GE => ">=",
+                            Comparator::GT => ">",
+                            Comparator::NE => "!=",
+                        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_program,repl,Instruction,Machine,Operand};
+
+    const EXAMPLE: &str = "b inc 5 if a > 1
+                           a inc 1 if b < 5
+                           c dec -10 if a >= 1
+                           c inc -20 if c == 10";
+
+    #[test]
+    fn puzzle_example() {
+        let mut machine = Machine::new(parse_program(EXAMPLE.as_bytes())
+                                           .unwrap());
+        assert!(machine.step().unwrap());
+        assert_eq!(machine.registers(), vec![]);
+        assert_eq!(machine.run().unwrap(), 3);
+        assert!(machine.is_finished());
+        assert_eq!(machine.largest(), Some(("a", 1)));
+        assert_eq!(machine.largest_ever(), 10);
+        assert_eq!(machine.max_value("c"), 10);
+    }
+
+    #[test]
+    fn extended_syntax_round_trips() {
+        for text in &["total_count set step", "x mul -3 if 1 != y",
+                      "a dec 2"] {
+            let inst = Instruction::parse(text).unwrap();
+            assert_eq!(inst.to_string(), *text);
+        }
+        assert!(Instruction::parse("a div 2").is_err());
+    }
+
+    #[test]
+    fn only_numbers_take_a_minus_sign() {
+        for text in &["a inc -b", "inc -b 5", "a inc 5 if -b > 1",
+                      "a inc 5 if b > -c"] {
+            assert!(Instruction::parse(text).is_err(), "{}", text);
+        }
+        let inst = Instruction::parse("a dec -5 if b > -1").unwrap();
+        assert_eq!(inst.amount, Operand::Value(-5));
+        assert_eq!(inst.cond.unwrap().right, Operand::Value(-1));
+    }
+
+    #[test]
+    fn repl_scripts() {
+        let script = "# powers of three
+                      n set 1
+                      n mul 3 if n < 20
+                      n mul 3 if n < 20
+                      n mul 3 if n < 20
+                      n mul 3 if n < 20
+                      :print n
+                      big set 9223372036854775807
+                      big inc 1
+                      :regs
+                      :reset
+                      :print n";
+        let mut output = Vec::new();
+        repl(script.as_bytes(), &mut output, false).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(),
+                   "n = 1\nn = 3\nn = 9\nn = 27\nskipped\nn = 27\n\
+                    big = 9223372036854775807\n\
+                    error: overflow in \"big inc 1\"\n\
+                    big = 9223372036854775807\nn = 27\nn = 0\n");
+    }
+}
@@ -0,0 +1,120 @@
+use util::grid::{extend_bounds,Grid,IPos};
+use std::ops::{Index,IndexMut};
+use std::vec;
+
+// A Grid stored as one Vec per row, with rows and columns interleaved so
+// that negative positions fit: 0, -1, 1, -2, 2 and so on. This was
+// InfiniteGrid's storage before it moved to tiles, and is kept to compare
+// against; every row is resized whenever the grid widens.
+#[allow(dead_code)]
+#[derive(Clone)]
+pub struct DenseGrid<T: Clone> {
+    rows: Vec<Vec<T>>,
+    default: T,
+    // The smallest and largest row and column written to, if any.
+    bounds: Option<(IPos, IPos)>
+}
+
+#[allow(dead_code)]
+impl<T: Clone> DenseGrid<T> {
+    pub fn new(default: T) -> DenseGrid<T> {
+        DenseGrid {
+            rows: Vec::new(),
+            default: default,
+            bounds: None
+        }
+    }
+
+    // Ensure the underlying vectors have min_rows and min_cols capacity.
+    fn ensure_capacity(&mut self, min_rows: usize, min_cols: usize) {
+        let orig_num_rows = self.rows.len();
+        if orig_num_rows < min_rows {
+            self.rows.reserve(min_rows - orig_num_rows);
+            for _ in orig_num_rows..min_rows {
+                self.rows.push(Vec::with_capacity(min_cols));
+            }
+        }
+        for row in 0..min_rows {
+            if self.rows[row].len() < min_cols {
+                self.rows[row].resize(min_cols, self.default.clone());
+            }
+        }
+    }
+
+    // Map a position (in one dimension) from the infinite (isize range) space
+    // to a natural number index (usize) for the underlying Vec.
+    fn pos_to_index(pos: isize) -> usize {
+        let result = if pos < 0 {
+                         (-pos*2) - 1
+                     } else {
+                         pos * 2
+                     };
+        result as usize
+    }
+
+    // The inverse of pos_to_index.
+    fn index_to_pos(index: usize) -> isize {
+        if index % 2 == 1 {
+            -(((index + 1) / 2) as isize)
+        } else {
+            (index / 2) as isize
+        }
+    }
+}
+
+impl<T: Clone> Grid<T> for DenseGrid<T> {
+    fn bounds(&self) -> Option<(IPos, IPos)> {
+        self.bounds
+    }
+
+    fn iter_mut<'a>(&'a mut self) -> vec::IntoIter<(IPos, &'a mut T)> {
+        let (min, max) = match self.bounds {
+            Some(bounds) => bounds,
+            None => return Vec::new().into_iter()
+        };
+        // Rows are as long as their furthest write needed, so positions past
+        // a row's end are left out rather than filled in.
+        let mut cells: Vec<(IPos, &mut T)> = Vec::new();
+        for (row_index, row) in self.rows.iter_mut().enumerate() {
+            let r = Self::index_to_pos(row_index);
+            if r < min.row || r > max.row {
+                continue;
+            }
+            for (col_index, value) in row.iter_mut().enumerate() {
+                let c = Self::index_to_pos(col_index);
+                if c >= min.col && c <= max.col {
+                    cells.push((ipos!(r, c), value));
+                }
+            }
+        }
+        cells.sort_by_key(|&(pos, _)| (pos.row, pos.col));
+        cells.into_iter()
+    }
+}
+
+// Index into the grid at the position (row, col).
+impl<T: Clone> Index<IPos> for DenseGrid<T> {
+    type Output = T;
+
+    fn index<'a>(&'a self, pos: IPos) -> &'a T {
+        let row_index = Self::pos_to_index(pos.row);
+        let col_index = Self::pos_to_index(pos.col);
+        if self.rows.len() <= row_index ||
+           self.rows[row_index].len() <= col_index {
+            return &self.default
+        }
+        &self.rows[row_index][col_index]
+    }
+}
+
+// Write into the grid at the position (row, col).
+impl<T: Clone> IndexMut<IPos> for DenseGrid<T> {
+    fn index_mut<'a>(&'a mut self, pos: IPos) -> &'a mut T {
+        let row_index = Self::pos_to_index(pos.row);
+        let col_index = Self::pos_to_index(pos.col);
+        self.ensure_capacity(row_index+1, col_index+1);
+        self.bounds = extend_bounds(self.bounds, pos);
+        &mut self.rows[row_index][col_index]
+    }
+}
+
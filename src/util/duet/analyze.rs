@@ -0,0 +1,756 @@
+use std::cmp;
+use std::collections::{BTreeSet,HashMap};
+use std::fmt;
+use std::isize;
+use super::{Arithmetic,Instruction,Operand};
+use super::decode::{self,Arg,Op};
+
+// The values a register might hold: every v with low <= v <= high. The ends
+// are wider than isize so that an unbounded end, -INFINITY or INFINITY, is
+// never confused with a register that really holds isize::MIN or MAX.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct Interval {
+    low: i128,
+    high: i128
+}
+
+// Beyond any isize, and any product of two.
+const INFINITY: i128 = i128::MAX;
+
+fn is_infinite(v: i128) -> bool {
+    v == INFINITY || v == -INFINITY
+}
+
+impl Interval {
+    pub fn new(low: isize, high: isize) -> Self {
+        Interval {
+            low: low as i128,
+            high: high as i128
+        }
+    }
+
+    pub fn constant(v: isize) -> Self {
+        Interval::new(v, v)
+    }
+
+    // Every value from `low` up, with no known upper bound.
+    pub fn at_least(low: isize) -> Self {
+        Interval {
+            low: low as i128,
+            high: INFINITY
+        }
+    }
+
+    pub fn at_most(high: isize) -> Self {
+        Interval {
+            low: -INFINITY,
+            high: high as i128
+        }
+    }
+
+    pub fn top() -> Self {
+        Interval {
+            low: -INFINITY,
+            high: INFINITY
+        }
+    }
+
+    // Ends past either limit of isize become unbounded.
+    fn beyond(low: i128, high: i128) -> Self {
+        Interval {
+            low: if low < isize::MIN as i128 { -INFINITY } else { low },
+            high: if high > isize::MAX as i128 { INFINITY } else { high }
+        }
+    }
+
+    pub fn is_top(&self) -> bool {
+        *self == Interval::top()
+    }
+
+    pub fn value(&self) -> Option<isize> {
+        if self.low == self.high { Some(self.low as isize) } else { None }
+    }
+
+    pub fn contains(&self, v: isize) -> bool {
+        self.low <= v as i128 && v as i128 <= self.high
+    }
+
+    fn join(&self, other: &Interval) -> Interval {
+        Interval {
+            low: cmp::min(self.low, other.low),
+            high: cmp::max(self.high, other.high)
+        }
+    }
+
+    fn meet(&self, other: &Interval) -> Option<Interval> {
+        let low = cmp::max(self.low, other.low);
+        let high = cmp::min(self.high, other.high);
+        if low <= high {
+            Some(Interval { low: low, high: high })
+        } else {
+            None
+        }
+    }
+
+    // Move any end that changed since `old` out to the next of `thresholds`
+    // (sorted), or give up on it, so that loops converge.
+    fn widen(&self, old: &Interval, thresholds: &[isize]) -> Interval {
+        let low = if self.low < old.low {
+            thresholds.iter()
+                      .rev()
+                      .map(|&t| t as i128)
+                      .find(|&t| t <= self.low)
+                      .unwrap_or(-INFINITY)
+        } else {
+            self.low
+        };
+        let high = if self.high > old.high {
+            thresholds.iter()
+                      .map(|&t| t as i128)
+                      .find(|&t| t >= self.high)
+                      .unwrap_or(INFINITY)
+        } else {
+            self.high
+        };
+        Interval { low: low, high: high }
+    }
+
+    // The values other than zero, if they still form an interval.
+    fn nonzero(&self) -> Option<Interval> {
+        if self.low == 0 && self.high == 0 {
+            None
+        } else if self.low == 0 {
+            Some(Interval { low: 1, high: self.high })
+        } else if self.high == 0 {
+            Some(Interval { low: self.low, high: -1 })
+        } else {
+            Some(*self)
+        }
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(v) = self.value() {
+            return write!(f, "{}", v);
+        }
+        let low = if self.low == -INFINITY {
+            "-inf".to_string()
+        } else {
+            self.low.to_string()
+        };
+        let high = if self.high == INFINITY {
+            "inf".to_string()
+        } else {
+            self.high.to_string()
+        };
+        write!(f, "[{}, {}]", low, high)
+    }
+}
+
+// Something that might go wrong when the program runs.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Warning {
+    // A jump or call might land outside the program (other than just past
+    // its end, which is how programs finish).
+    JumpOutOfRange { pc: usize, instruction: Instruction, targets: Interval },
+    // A div or mod might divide by zero.
+    DivisionByZero { pc: usize, instruction: Instruction, divisor: Interval }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Warning::JumpOutOfRange { pc, instruction, targets } => {
+                write!(f, "pc {}: \"{}\" may jump out of range (to {})",
+                       pc, instruction, targets)
+            },
+            Warning::DivisionByZero { pc, instruction, divisor } => {
+                write!(f, "pc {}: \"{}\" may divide by zero (divisor {})",
+                       pc, instruction, divisor)
+            }
+        }
+    }
+}
+
+// Ranges of register values per register slot, or None where the pc can't
+// be reached.
+type State = Option<Vec<Interval>>;
+
+// How many times a pc's state may grow before its ranges are widened.
+const WIDEN_AFTER: usize = 3;
+// How many passes are spent tightening the ranges after widening.
+const NARROWING_PASSES: usize = 5;
+
+// What the analysis learned about every register at every pc, meaning
+// before the instruction at that pc runs.
+pub struct Analysis {
+    names: Vec<char>,
+    slots: HashMap<char, usize>,
+    states: Vec<State>,
+    pub warnings: Vec<Warning>
+}
+
+impl Analysis {
+    pub fn reachable(&self, pc: usize) -> bool {
+        self.states.get(pc).map_or(false, |s| s.is_some())
+    }
+
+    // The range of `r` at `pc`, or None if `pc` is unreachable. Registers the
+    // program never mentions are always zero.
+    #[allow(dead_code)]
+    pub fn range(&self, pc: usize, r: char) -> Option<Interval> {
+        match self.states.get(pc) {
+            Some(&Some(ref state)) => Some(match self.slots.get(&r) {
+                Some(&slot) => state[slot],
+                None => Interval::constant(0)
+            }),
+            _ => None
+        }
+    }
+
+    // The value `r` always has at `pc`, if it has just one.
+    #[allow(dead_code)]
+    pub fn constant(&self, pc: usize, r: char) -> Option<isize> {
+        self.range(pc, r).and_then(|i| i.value())
+    }
+
+    // Every register known to be somewhere narrower than any value at `pc`,
+    // in register order.
+    pub fn facts(&self, pc: usize) -> Vec<(char, Interval)> {
+        let mut facts = match self.states.get(pc) {
+            Some(&Some(ref state)) => {
+                self.names.iter()
+                          .cloned()
+                          .zip(state.iter().cloned())
+                          .filter(|&(_, i)| !i.is_top())
+                          .collect()
+            },
+            _ => Vec::new()
+        };
+        facts.sort_by_key(|&(r, _)| r);
+        facts
+    }
+}
+
+// Work out ranges for every register at every pc by abstract interpretation
+// over intervals. Registers start at zero, except those in `initial`. Extended
+// instructions are analyzed as the extended dialect runs them; rcv and pop
+// can produce any value.
+//
+// Like the interpreter, the analysis tracks each register separately, so
+// relations between registers are lost: a loop counter compared against
+// another register is generally only known to be bounded on one side.
+pub fn analyze(instructions: &[Instruction], initial: &[(char, Interval)],
+               arithmetic: Arithmetic) -> Analysis {
+    let decoded = decode::decode(instructions);
+    let mut entry = vec![Interval::constant(0); decoded.names.len()];
+    for &(r, range) in initial {
+        if let Some(&slot) = decoded.slots.get(&r) {
+            entry[slot] = range;
+        }
+    }
+    let mut analyzer = Analyzer {
+        ops: decoded.ops,
+        instructions: instructions,
+        arithmetic: arithmetic,
+        returns: Vec::new(),
+        thresholds: Vec::new()
+    };
+    analyzer.returns = analyzer.ops
+                               .iter()
+                               .enumerate()
+                               .filter(|&(_, op)| match *op {
+                                   Op::Call(_) => true,
+                                   _ => false
+                               })
+                               .map(|(pc, _)| pc + 1)
+                               .collect();
+    let mut thresholds: Vec<isize> = vec![-1, 0, 1];
+    for inst in instructions {
+        for operand in inst.operands() {
+            if let Operand::Value(v) = operand {
+                thresholds.push(v);
+            }
+        }
+    }
+    thresholds.sort();
+    thresholds.dedup();
+    analyzer.thresholds = thresholds;
+    let states = analyzer.solve(entry);
+    let warnings = analyzer.warnings(&states);
+    Analysis {
+        names: decoded.names,
+        slots: decoded.slots,
+        states: states,
+        warnings: warnings
+    }
+}
+
+struct Analyzer<'a> {
+    ops: Vec<Op>,
+    instructions: &'a [Instruction],
+    arithmetic: Arithmetic,
+    // Where a ret might go: just after any call.
+    returns: Vec<usize>,
+    // Every constant in the program, sorted, which makes for likely bounds
+    // when widening: a flag set to 0 or 1 stays within [0, 1].
+    thresholds: Vec<isize>
+}
+
+impl<'a> Analyzer<'a> {
+    fn solve(&self, entry: Vec<Interval>) -> Vec<State> {
+        let len = self.ops.len();
+        let mut states: Vec<State> = vec![None; len];
+        if len == 0 {
+            return states;
+        }
+        let mut updates = vec![0; len];
+        let mut worklist: BTreeSet<usize> = BTreeSet::new();
+        states[0] = Some(entry.clone());
+        worklist.insert(0);
+        while let Some(&pc) = worklist.iter().next() {
+            worklist.remove(&pc);
+            let state = states[pc].clone().unwrap();
+            for (next, incoming) in self.successors(pc, &state) {
+                let merged = match states[next] {
+                    None => incoming,
+                    Some(ref old) => {
+                        let joined = join(old, &incoming);
+                        if joined == *old {
+                            continue;
+                        }
+                        updates[next] += 1;
+                        if updates[next] > WIDEN_AFTER {
+                            joined.iter()
+                                  .zip(old.iter())
+                                  .map(|(new, old)| {
+                                      new.widen(old, &self.thresholds)
+                                  })
+                                  .collect()
+                        } else {
+                            joined
+                        }
+                    }
+                };
+                states[next] = Some(merged);
+                worklist.insert(next);
+            }
+        }
+
+        // Widening overshoots, e.g. a countdown from 10 ends up unbounded
+        // below. Recomputing each state from its predecessors recovers what
+        // the loop conditions guarantee.
+        for _ in 0..NARROWING_PASSES {
+            let mut incoming: Vec<State> = vec![None; len];
+            incoming[0] = Some(entry.clone());
+            for pc in 0..len {
+                if let Some(ref state) = states[pc] {
+                    for (next, values) in self.successors(pc, state) {
+                        incoming[next] = Some(match incoming[next] {
+                            Some(ref old) => join(old, &values),
+                            None => values
+                        });
+                    }
+                }
+            }
+            if incoming == states {
+                break;
+            }
+            states = incoming;
+        }
+        states
+    }
+
+    // The states that executing `pc` in `state` can lead to, by pc. Only
+    // pcs inside the program are included.
+    fn successors(&self, pc: usize, state: &[Interval])
+            -> Vec<(usize, Vec<Interval>)> {
+        let mut result = Vec::new();
+        let mut next = |pc: usize, values: Vec<Interval>| {
+            if pc < self.ops.len() {
+                result.push((pc, values));
+            }
+        };
+        match self.ops[pc] {
+            Op::Add(r, arg) | Op::Sub(r, arg) | Op::Multiply(r, arg) |
+            Op::Mod(r, arg) | Op::Divide(r, arg) => {
+                let b = value(state, arg);
+                let v = match self.ops[pc] {
+                    Op::Add(..) => Some(self.add(state[r], b)),
+                    Op::Sub(..) => Some(self.add(state[r], negate(b))),
+                    Op::Multiply(..) => Some(self.multiply(state[r], b)),
+                    Op::Mod(..) => remainder(state[r], b),
+                    _ => self.divide(state[r], b)
+                };
+                if let Some(v) = v {
+                    next(pc + 1, with(state, r, v));
+                }
+            },
+            Op::Set(r, arg) => next(pc + 1, with(state, r, value(state, arg))),
+            Op::Receive(r) | Op::Pop(r) => {
+                next(pc + 1, with(state, r, Interval::top()));
+            },
+            Op::Send(_) | Op::Out(_) | Op::Push(_) => {
+                next(pc + 1, state.to_vec());
+            },
+            Op::JumpGZ(condition, offset) | Op::JumpNZ(condition, offset) => {
+                let greater = match self.ops[pc] {
+                    Op::JumpGZ(..) => true,
+                    _ => false
+                };
+                let v = value(state, condition);
+                let (taken, fallen) = if greater {
+                    (v.meet(&Interval::at_least(1)),
+                     v.meet(&Interval::at_most(0)))
+                } else {
+                    (v.nonzero(), v.meet(&Interval::constant(0)))
+                };
+                if let Some(v) = fallen {
+                    next(pc + 1, refine(state, condition, v));
+                }
+                if let Some(v) = taken {
+                    let state = refine(state, condition, v);
+                    for target in self.targets(pc, value(&state, offset)) {
+                        next(target, state.clone());
+                    }
+                }
+            },
+            Op::Call(offset) => {
+                for target in self.targets(pc, value(state, offset)) {
+                    next(target, state.to_vec());
+                }
+            },
+            Op::Return => {
+                for &target in &self.returns {
+                    next(target, state.to_vec());
+                }
+            },
+            Op::Invalid => {}
+        }
+        result
+    }
+
+    // The pcs inside the program that a jump by `offset` from `pc` can reach.
+    fn targets(&self, pc: usize, offset: Interval) -> Vec<usize> {
+        let low = cmp::max((pc as i128).saturating_add(offset.low), 0);
+        let high = cmp::min((pc as i128).saturating_add(offset.high),
+                            self.ops.len() as i128 - 1);
+        (low..high + 1).map(|t| t as usize).collect()
+    }
+
+    fn warnings(&self, states: &[State]) -> Vec<Warning> {
+        let mut warnings = Vec::new();
+        for (pc, state) in states.iter().enumerate() {
+            let state = match *state {
+                Some(ref state) => state,
+                None => continue
+            };
+            let instruction = self.instructions[pc];
+            let jump = match self.ops[pc] {
+                Op::JumpGZ(condition, offset) => {
+                    value(state, condition)
+                        .meet(&Interval::at_least(1))
+                        .map(|v| value(&refine(state, condition, v), offset))
+                },
+                Op::JumpNZ(condition, offset) => {
+                    value(state, condition)
+                        .nonzero()
+                        .map(|v| value(&refine(state, condition, v), offset))
+                },
+                Op::Call(offset) => Some(value(state, offset)),
+                Op::Mod(_, arg) | Op::Divide(_, arg) => {
+                    let divisor = value(state, arg);
+                    if divisor.contains(0) {
+                        warnings.push(Warning::DivisionByZero {
+                            pc: pc,
+                            instruction: instruction,
+                            divisor: divisor
+                        });
+                    }
+                    None
+                },
+                _ => None
+            };
+            if let Some(offset) = jump {
+                let low = (pc as i128).saturating_add(offset.low);
+                let high = (pc as i128).saturating_add(offset.high);
+                if low < 0 || high > self.ops.len() as i128 {
+                    warnings.push(Warning::JumpOutOfRange {
+                        pc: pc,
+                        instruction: instruction,
+                        targets: Interval::beyond(low, high)
+                    });
+                }
+            }
+        }
+        warnings
+    }
+
+    fn add(&self, a: Interval, b: Interval) -> Interval {
+        self.fit(plus(a.low, b.low), plus(a.high, b.high))
+    }
+
+    fn multiply(&self, a: Interval, b: Interval) -> Interval {
+        let products = [times(a.low, b.low), times(a.low, b.high),
+                        times(a.high, b.low), times(a.high, b.high)];
+        self.fit(*products.iter().min().unwrap(),
+                 *products.iter().max().unwrap())
+    }
+
+    // Division rounds towards zero, so within each sign of divisor the
+    // extremes are at the corners.
+    fn divide(&self, a: Interval, b: Interval) -> Option<Interval> {
+        let negative = b.meet(&Interval::at_most(-1));
+        let positive = b.meet(&Interval::at_least(1));
+        let mut low: Option<i128> = None;
+        let mut high: Option<i128> = None;
+        for part in negative.iter().chain(positive.iter()) {
+            for &x in &[a.low, a.high] {
+                for &y in &[part.low, part.high] {
+                    let q = quotient(x, y);
+                    low = Some(low.map_or(q, |l| cmp::min(l, q)));
+                    high = Some(high.map_or(q, |h| cmp::max(h, q)));
+                }
+            }
+        }
+        match (low, high) {
+            (Some(low), Some(high)) => Some(self.fit(low, high)),
+            _ => None
+        }
+    }
+
+    // Bring a result computed without overflow back into isize, the way
+    // the Arithmetic setting would. Results that fail under Checked don't
+    // carry on, so clamping them covers every value that does. Unbounded
+    // ends stay unbounded, except that wrapping could take them anywhere.
+    fn fit(&self, low: i128, high: i128) -> Interval {
+        let min = isize::MIN as i128;
+        let max = isize::MAX as i128;
+        if low >= min && high <= max {
+            return Interval { low: low, high: high };
+        }
+        match self.arithmetic {
+            Arithmetic::Checked | Arithmetic::Saturating => {
+                Interval { low: clamp(low), high: clamp(high) }
+            },
+            Arithmetic::Wrapping => Interval::top()
+        }
+    }
+}
+
+// Arithmetic on interval ends, where an unbounded end stays unbounded:
+// "at least 105700, minus 17" is "at least 105683". Both ends of a sum are
+// never unbounded in opposite directions, as it adds low to low and high
+// to high.
+fn plus(x: i128, y: i128) -> i128 {
+    if is_infinite(x) {
+        x
+    } else if is_infinite(y) {
+        y
+    } else {
+        x + y
+    }
+}
+
+fn times(x: i128, y: i128) -> i128 {
+    if x == 0 || y == 0 {
+        0
+    } else if is_infinite(x) || is_infinite(y) {
+        if (x < 0) == (y < 0) { INFINITY } else { -INFINITY }
+    } else {
+        x * y
+    }
+}
+
+// `y` is never zero. An unbounded divisor can make the quotient as small as
+// zero; dividing one unbounded end by another is only ever a corner between
+// two others, so any value of the right sign will do.
+fn quotient(x: i128, y: i128) -> i128 {
+    let sign = if (x < 0) == (y < 0) { 1 } else { -1 };
+    match (is_infinite(x), is_infinite(y)) {
+        (true, true) => sign,
+        (false, true) => 0,
+        (true, false) => sign * INFINITY,
+        (false, false) => x / y
+    }
+}
+
+// Clamp a bounded end into isize.
+fn clamp(v: i128) -> i128 {
+    if is_infinite(v) {
+        v
+    } else {
+        cmp::max(cmp::min(v, isize::MAX as i128), isize::MIN as i128)
+    }
+}
+
+// The remainder takes the dividend's sign and is smaller than the divisor.
+fn remainder(a: Interval, b: Interval) -> Option<Interval> {
+    let b = b.nonzero()?;
+    if let (Some(x), Some(y)) = (a.value(), b.value()) {
+        return Some(Interval::constant(x.wrapping_rem(y)));
+    }
+    let smallest = if b.contains(0) {
+        1
+    } else {
+        cmp::min(b.low.abs(), b.high.abs())
+    };
+    let largest = cmp::max(b.low.abs(), b.high.abs());
+    if a.low >= 0 && a.high < smallest {
+        return Some(a);
+    }
+    let limit = if is_infinite(largest) { largest } else { largest - 1 };
+    let low = if a.low >= 0 { 0 } else { cmp::max(a.low, -limit) };
+    let high = if a.high <= 0 { 0 } else { cmp::min(a.high, limit) };
+    Some(Interval { low: low, high: high })
+}
+
+// Exact, as the ends are wider than isize: negating isize::MIN gives a
+// value that fit() then treats as the overflow it is.
+fn negate(a: Interval) -> Interval {
+    Interval { low: -a.high, high: -a.low }
+}
+
+fn value(state: &[Interval], arg: Arg) -> Interval {
+    match arg {
+        Arg::Slot(r) => state[r],
+        Arg::Value(v) => Interval::constant(v)
+    }
+}
+
+fn with(state: &[Interval], r: usize, v: Interval) -> Vec<Interval> {
+    let mut state = state.to_vec();
+    state[r] = v;
+    state
+}
+
+// The state after learning that `arg` is within `v`.
+fn refine(state: &[Interval], arg: Arg, v: Interval) -> Vec<Interval> {
+    match arg {
+        Arg::Slot(r) => with(state, r, v),
+        Arg::Value(_) => state.to_vec()
+    }
+}
+
+fn join(a: &[Interval], b: &[Interval]) -> Vec<Interval> {
+    a.iter().zip(b.iter()).map(|(x, y)| x.join(y)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{analyze,Interval,Warning};
+    use std::isize;
+    use util::duet::{parse_instructions,Arithmetic};
+    use util::duet::asm::assemble;
+
+    fn source(text: &str) -> Vec<::util::duet::Instruction> {
+        assemble(text).unwrap().instructions
+    }
+
+    #[test]
+    fn day23_constants_are_found() {
+        let instructions = parse_instructions("input/day23_orig.txt").unwrap();
+        let part_2 = analyze(&instructions, &[('a', Interval::constant(1))],
+                             Arithmetic::Checked);
+        // "mul b 100" and friends only run once, before the loops.
+        assert_eq!(part_2.constant(4, 'b'), Some(57));
+        assert_eq!(part_2.constant(8, 'c'), Some(122700));
+        assert_eq!(part_2.range(8, 'b'), Some(Interval::at_least(105700)));
+        // g = b - c, just before "jnz g 2" decides whether to stop.
+        assert_eq!(part_2.range(28, 'g'), Some(Interval::at_least(-17000)));
+        // Thresholds keep the flag within [0, 1] while widening.
+        assert_eq!(part_2.range(8, 'f'), Some(Interval::new(0, 1)));
+        assert!(part_2.warnings.is_empty());
+
+        let part_1 = analyze(&instructions, &[], Arithmetic::Checked);
+        assert!(!part_1.reachable(4));
+        assert_eq!(part_1.constant(8, 'c'), Some(57));
+    }
+
+    #[test]
+    fn loops_are_narrowed() {
+        let instructions = source("
+            set i 10
+            loop: sub i 1
+            jgz i loop
+            set j i
+        ");
+        let analysis = analyze(&instructions, &[], Arithmetic::Checked);
+        assert_eq!(analysis.range(1, 'i'), Some(Interval::new(1, 10)));
+        assert_eq!(analysis.constant(3, 'i'), Some(0));
+        assert_eq!(analysis.facts(3), vec![('i', Interval::constant(0)),
+                                           ('j', Interval::constant(0)),
+                                           ('p', Interval::constant(0))]);
+    }
+
+    #[test]
+    fn limits_of_isize_are_values() {
+        let instructions = source("
+            add a 1
+            sub a 1
+            sub b 1
+            add b 1
+            sub c 1
+            rcv d
+            add d 1
+        ");
+        let initial = [('a', Interval::constant(isize::MAX - 1)),
+                       ('b', Interval::constant(isize::MIN + 1)),
+                       ('c', Interval::new(0, isize::MAX))];
+        let analysis = analyze(&instructions, &initial, Arithmetic::Checked);
+        assert_eq!(analysis.constant(1, 'a'), Some(isize::MAX));
+        assert_eq!(analysis.constant(2, 'a'), Some(isize::MAX - 1));
+        assert_eq!(analysis.constant(3, 'b'), Some(isize::MIN));
+        assert_eq!(analysis.constant(4, 'b'), Some(isize::MIN + 1));
+        assert_eq!(analysis.range(5, 'c'),
+                   Some(Interval::new(-1, isize::MAX - 1)));
+        // Only values that really are unbounded stay that way.
+        assert_eq!(analysis.range(6, 'd'), Some(Interval::top()));
+        assert_eq!(analysis.range(6, 'c').unwrap().to_string(),
+                   format!("[-1, {}]", isize::MAX - 1));
+
+        let wrapping = analyze(&instructions, &initial, Arithmetic::Wrapping);
+        assert_eq!(wrapping.constant(2, 'a'), Some(isize::MAX - 1));
+    }
+
+    #[test]
+    fn day18_is_clean_for_both_programs() {
+        let instructions = parse_instructions("input/day18.txt").unwrap();
+        // Not as p in [0, 1]: then "jgz p p" could go anywhere in between.
+        for p in 0..2 {
+            let analysis = analyze(&instructions,
+                                   &[('p', Interval::constant(p))],
+                                   Arithmetic::Checked);
+            assert!(analysis.warnings.is_empty(), "{:?}", analysis.warnings);
+        }
+    }
+
+    #[test]
+    fn possible_failures_are_reported() {
+        let instructions = source("
+            rcv a
+            set b a
+            mod b 4
+            mod a b
+            mod a 3
+            jgz a b
+        ");
+        let analysis = analyze(&instructions, &[], Arithmetic::Wrapping);
+        let warnings: Vec<String> = analysis.warnings
+                                            .iter()
+                                            .map(|w| w.to_string())
+                                            .collect();
+        assert_eq!(warnings,
+                   vec!["pc 3: \"mod a b\" may divide by zero \
+                         (divisor [-3, 3])",
+                        "pc 5: \"jgz a b\" may jump out of range \
+                         (to [2, 8])"]);
+        match analysis.warnings[0] {
+            Warning::DivisionByZero { pc, .. } => assert_eq!(pc, 3),
+            _ => panic!("expected a division warning")
+        }
+        // After "mod a 3", a is in [-2, 2]; only positive values jump.
+        assert_eq!(analysis.range(5, 'a'), Some(Interval::new(-2, 2)));
+    }
+}
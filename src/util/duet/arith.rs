@@ -0,0 +1,154 @@
+use failure::{Error,Fail};
+use std::fmt;
+use std::isize;
+use std::str::FromStr;
+
+// What add, sub, mul, div and mod do with results that don't fit in an isize.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Arithmetic {
+    // Fail with an ArithmeticError.
+    Checked,
+    // Wrap around, as release builds did before this was configurable.
+    Wrapping,
+    // Clamp to isize::MIN or isize::MAX.
+    Saturating,
+}
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Operation {
+    Add,
+    Sub,
+    Multiply,
+    Divide,
+    Mod,
+}
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum ArithmeticErrorKind {
+    Overflow,
+    DivisionByZero,
+}
+
+// Returned (inside a failure::Error) when an instruction's arithmetic fails.
+// Division and mod by zero are errors whatever the Arithmetic setting. The
+// instruction is kept as text, with the program's register names.
+#[derive(Clone,Debug,PartialEq)]
+pub struct ArithmeticError {
+    pub kind: ArithmeticErrorKind,
+    pub pc: usize,
+    pub instruction: String
+}
+
+impl fmt::Display for ArithmeticError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let what = match self.kind {
+            ArithmeticErrorKind::Overflow => "overflow",
+            ArithmeticErrorKind::DivisionByZero => "division by zero"
+        };
+        write!(f, "{} in \"{}\" at pc {}", what, self.instruction, self.pc)
+    }
+}
+
+impl Fail for ArithmeticError {}
+
+impl FromStr for Arithmetic {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "checked"    => Ok(Arithmetic::Checked),
+            "wrapping"   => Ok(Arithmetic::Wrapping),
+            "saturating" => Ok(Arithmetic::Saturating),
+            _            => Err(format_err!("bad arithmetic: {}", s))
+        }
+    }
+}
+
+impl Arithmetic {
+    pub fn apply(&self, op: Operation, a: isize, b: isize)
+            -> Result<isize, ArithmeticErrorKind> {
+        if b == 0 && (op == Operation::Divide || op == Operation::Mod) {
+            return Err(ArithmeticErrorKind::DivisionByZero);
+        }
+        // The remainder of isize::MIN / -1 is 0; only the division overflows.
+        if op == Operation::Mod {
+            return Ok(a.wrapping_rem(b));
+        }
+        let checked = match op {
+            Operation::Add      => a.checked_add(b),
+            Operation::Sub      => a.checked_sub(b),
+            Operation::Multiply => a.checked_mul(b),
+            Operation::Divide   => a.checked_div(b),
+            Operation::Mod      => unreachable!()
+        };
+        match (checked, *self) {
+            (Some(v), _) => Ok(v),
+            (None, Arithmetic::Checked) => Err(ArithmeticErrorKind::Overflow),
+            (None, Arithmetic::Wrapping) => Ok(match op {
+                Operation::Add      => a.wrapping_add(b),
+                Operation::Sub      => a.wrapping_sub(b),
+                Operation::Multiply => a.wrapping_mul(b),
+                _                   => a.wrapping_div(b)
+            }),
+            (None, Arithmetic::Saturating) => Ok(match op {
+                Operation::Add      => a.saturating_add(b),
+                Operation::Sub      => a.saturating_sub(b),
+                Operation::Multiply => a.saturating_mul(b),
+                // Only isize::MIN / -1 overflows.
+                _                   => isize::MAX
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Arithmetic,ArithmeticError,ArithmeticErrorKind,Operation};
+    use std::isize;
+    use util::duet::Program;
+    use util::duet::Instruction::*;
+    use util::duet::Operand::*;
+
+    #[test]
+    fn policies_differ_only_on_overflow() {
+        for policy in &[Arithmetic::Checked, Arithmetic::Wrapping,
+                        Arithmetic::Saturating] {
+            assert_eq!(policy.apply(Operation::Multiply, 6, -7), Ok(-42));
+            assert_eq!(policy.apply(Operation::Mod, -7, 3), Ok(-1));
+            assert_eq!(policy.apply(Operation::Mod, 7, 0),
+                       Err(ArithmeticErrorKind::DivisionByZero));
+        }
+        let max = isize::MAX;
+        assert_eq!(Arithmetic::Checked.apply(Operation::Add, max, 1),
+                   Err(ArithmeticErrorKind::Overflow));
+        assert_eq!(Arithmetic::Wrapping.apply(Operation::Add, max, 1),
+                   Ok(isize::MIN));
+        assert_eq!(Arithmetic::Saturating.apply(Operation::Sub, -max, 2),
+                   Ok(isize::MIN));
+        assert_eq!(Arithmetic::Saturating.apply(Operation::Divide,
+                                                isize::MIN, -1),
+                   Ok(max));
+    }
+
+    #[test]
+    fn errors_carry_the_pc() {
+        let instructions = vec![Set(Register('a'), Value(isize::MAX)),
+                                Multiply(Register('a'), Value(2))];
+        let mut program = Program::new(0, &instructions);
+        program.step().unwrap();
+        let error = program.step().unwrap_err();
+        assert_eq!(error.downcast_ref::<ArithmeticError>(),
+                   Some(&ArithmeticError {
+                       kind: ArithmeticErrorKind::Overflow,
+                       pc: 1,
+                       instruction: "mul a 2".to_string()
+                   }));
+        assert_eq!(error.to_string(), "overflow in \"mul a 2\" at pc 1");
+
+        let instructions = vec![Mod(Register('a'), Register('b'))];
+        let mut program = Program::new(0, &instructions);
+        program.set_arithmetic(Arithmetic::Wrapping);
+        assert_eq!(program.step().unwrap_err().to_string(),
+                   "division by zero in \"mod a b\" at pc 0");
+    }
+}
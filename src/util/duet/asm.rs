@@ -0,0 +1,370 @@
+use failure::Error;
+use std::char;
+use std::collections::{BTreeMap,HashMap};
+use super::{disasm,Dialect,Instruction,Operand};
+
+// Registers with names longer than one character are given characters from
+// the Unicode private use area, starting here.
+pub const FIRST_NAMED_REGISTER: u32 = 0xE000;
+const LAST_NAMED_REGISTER: u32 = 0xF8FF;
+
+// For a register allocated to a multi-character name, its allocation order.
+pub fn named_register_index(r: char) -> Option<u32> {
+    let code = r as u32;
+    if code >= FIRST_NAMED_REGISTER && code <= LAST_NAMED_REGISTER {
+        Some(code - FIRST_NAMED_REGISTER)
+    } else {
+        None
+    }
+}
+
+// The result of assembling a duet source file.
+pub struct Assembly {
+    pub instructions: Vec<Instruction>,
+    // The register character allocated to each multi-character name.
+    pub registers: BTreeMap<String, char>
+}
+
+impl Assembly {
+    // Turn the instructions back into source, using the original register
+    // names; see disasm::disassemble.
+    pub fn disassemble(&self, labels: bool) -> String {
+        disasm::disassemble(&self.instructions, &self.registers, labels)
+    }
+
+    // The register a name in the source refers to.
+    pub fn register(&self, name: &str) -> Result<char, Error> {
+        let mut chars = name.chars();
+        match (self.registers.get(name), chars.next(), chars.next()) {
+            (Some(&r), _, _) => Ok(r),
+            (None, Some(r), None) => Ok(r),
+            _ => Err(format_err!("unknown register: {}", name))
+        }
+    }
+}
+
+// Assemble duet source text. On top of the plain puzzle syntax this accepts:
+//
+//   # comments, to the end of any line
+//   blank lines
+//   loop:            labels, on their own line or before an instruction,
+//   jnz a loop       usable as the target of jgz/jnz
+//   .const N 17      named constants, usable anywhere a value is
+//   add count 1      register names longer than one character
+//
+// Only the puzzle's instructions are accepted; see assemble_dialect for the
+// extended dialect.
+pub fn assemble(source: &str) -> Result<Assembly, Error> {
+    assemble_dialect(source, Dialect::Strict)
+}
+
+// Assemble source written for the given dialect. The extended dialect adds:
+//
+//   div a b          a = a / b, rounding towards zero
+//   push a           push a value onto the stack
+//   pop a            pop the stack into a register
+//   call f           push the return address and jump (labels allowed)
+//   ret              return to the most recent call
+//   out a            print a value
+pub fn assemble_dialect(source: &str, dialect: Dialect)
+        -> Result<Assembly, Error> {
+    let mut assembler = Assembler {
+        dialect: dialect,
+        labels: HashMap::new(),
+        consts: HashMap::new(),
+        registers: BTreeMap::new()
+    };
+    let lines = assembler.collect_lines(source)?;
+    let mut instructions = Vec::new();
+    for (index, line) in lines.iter().enumerate() {
+        let instruction = assembler.lower(index, line)
+                                   .map_err(|e| format_err!("line {}: {}",
+                                                            line.number, e))?;
+        instructions.push(instruction);
+    }
+    Ok(Assembly {
+        instructions: instructions,
+        registers: assembler.registers
+    })
+}
+
+// An instruction's tokens, before operands are resolved.
+struct Line<'a> {
+    number: usize,
+    mnemonic: &'a str,
+    operands: Vec<&'a str>
+}
+
+struct Assembler {
+    dialect: Dialect,
+    labels: HashMap<String, usize>,
+    consts: HashMap<String, isize>,
+    registers: BTreeMap<String, char>
+}
+
+impl Assembler {
+    // First pass: strip comments, record labels and constants, and split the
+    // remaining instructions into tokens.
+    fn collect_lines<'a>(&mut self, source: &'a str)
+            -> Result<Vec<Line<'a>>, Error> {
+        let mut lines = Vec::new();
+        for (i, raw) in source.lines().enumerate() {
+            let number = i + 1;
+            let mut rest = match raw.find('#') {
+                Some(pos) => &raw[..pos],
+                None => raw
+            }.trim();
+            while let Some(colon) = rest.find(':') {
+                let label = rest[..colon].trim();
+                if !is_identifier(label) {
+                    return Err(format_err!("line {}: bad label: {}",
+                                           number, label));
+                }
+                if self.labels.contains_key(label) ||
+                   self.consts.contains_key(label) {
+                    return Err(format_err!("line {}: {} is already defined",
+                                           number, label));
+                }
+                self.labels.insert(label.to_string(), lines.len());
+                rest = rest[colon+1..].trim();
+            }
+            if rest.is_empty() {
+                continue;
+            }
+            let tokens: Vec<&str> = rest.split_whitespace().collect();
+            if tokens[0] == ".const" {
+                self.define_const(&tokens[1..])
+                    .map_err(|e| format_err!("line {}: {}", number, e))?;
+                continue;
+            }
+            lines.push(Line {
+                number: number,
+                mnemonic: tokens[0],
+                operands: tokens[1..].to_vec()
+            });
+        }
+        Ok(lines)
+    }
+
+    fn define_const(&mut self, tokens: &[&str]) -> Result<(), Error> {
+        if tokens.len() != 2 {
+            return Err(format_err!(".const takes a name and a value"));
+        }
+        if !is_identifier(tokens[0]) {
+            return Err(format_err!("bad constant name: {}", tokens[0]));
+        }
+        if self.labels.contains_key(tokens[0]) ||
+           self.consts.contains_key(tokens[0]) {
+            return Err(format_err!("{} is already defined", tokens[0]));
+        }
+        let value = match self.value(tokens[1]) {
+            Some(value) => value,
+            None => return Err(format_err!("bad constant value: {}",
+                                           tokens[1]))
+        };
+        self.consts.insert(tokens[0].to_string(), value);
+        Ok(())
+    }
+
+    // Second pass: turn the instruction at `index` into an Instruction.
+    fn lower(&mut self, index: usize, line: &Line)
+            -> Result<Instruction, Error> {
+        let (arity, extended) = match line.mnemonic {
+            "rcv" | "snd" => (1, false),
+            "add" | "jgz" | "jnz" | "mod" | "mul" | "set" | "sub" => (2, false),
+            "ret" => (0, true),
+            "call" | "out" | "pop" | "push" => (1, true),
+            "div" => (2, true),
+            _ => return Err(format_err!("unrecognized instruction: {}",
+                                        line.mnemonic))
+        };
+        if extended && self.dialect != Dialect::Extended {
+            return Err(format_err!("{} is only available in the extended \
+                                    dialect", line.mnemonic));
+        }
+        if line.operands.len() != arity {
+            return Err(format_err!("{} takes {} operand(s), found {}",
+                                   line.mnemonic, arity, line.operands.len()));
+        }
+        if line.mnemonic == "ret" {
+            return Ok(Instruction::Return);
+        }
+        let jump = line.mnemonic == "jgz" || line.mnemonic == "jnz";
+        let call = line.mnemonic == "call";
+        let op1 = self.operand(line.operands[0],
+                               if call { Some(index) } else { None })?;
+        let op2 = if arity == 2 {
+            let target = if jump { Some(index) } else { None };
+            Some(self.operand(line.operands[1], target)?)
+        } else {
+            None
+        };
+        let reads_only = ["call", "jgz", "jnz", "out", "push", "snd"];
+        if !reads_only.contains(&line.mnemonic) {
+            if let Operand::Value(_) = op1 {
+                return Err(format_err!("{} needs a register to write to",
+                                       line.mnemonic));
+            }
+        }
+        Ok(match line.mnemonic {
+            "add"  => Instruction::Add(op1, op2.unwrap()),
+            "call" => Instruction::Call(op1),
+            "div"  => Instruction::Divide(op1, op2.unwrap()),
+            "jgz"  => Instruction::JumpGZ(op1, op2.unwrap()),
+            "jnz"  => Instruction::JumpNZ(op1, op2.unwrap()),
+            "mod"  => Instruction::Mod(op1, op2.unwrap()),
+            "mul"  => Instruction::Multiply(op1, op2.unwrap()),
+            "out"  => Instruction::Out(op1),
+            "pop"  => Instruction::Pop(op1),
+            "push" => Instruction::Push(op1),
+            "rcv"  => Instruction::Receive(op1),
+            "set"  => Instruction::Set(op1, op2.unwrap()),
+            "snd"  => Instruction::Send(op1),
+            "sub"  => Instruction::Sub(op1, op2.unwrap()),
+            _      => unreachable!()
+        })
+    }
+
+    // Resolve an operand token. Labels are only allowed as jump and call
+    // targets, in which case `jump_from` is the index of the jump instruction
+    // and the label becomes a relative offset.
+    fn operand(&mut self, token: &str, jump_from: Option<usize>)
+            -> Result<Operand, Error> {
+        if let Some(value) = self.value(token) {
+            return Ok(Operand::Value(value));
+        }
+        if let Some(&target) = self.labels.get(token) {
+            return match jump_from {
+                Some(from) => Ok(Operand::Value(target as isize -
+                                                from as isize)),
+                None => Err(format_err!("label {} can only be a jump target",
+                                        token))
+            };
+        }
+        if is_identifier(token) {
+            return Ok(Operand::Register(self.register(token)?));
+        }
+        Err(format_err!("bad operand: {}", token))
+    }
+
+    // A literal number or a constant's value.
+    fn value(&self, token: &str) -> Option<isize> {
+        match token.parse::<isize>() {
+            Ok(value) => Some(value),
+            Err(_) => self.consts.get(token).cloned()
+        }
+    }
+
+    fn register(&mut self, name: &str) -> Result<char, Error> {
+        let mut chars = name.chars();
+        if let (Some(r), None) = (chars.next(), chars.next()) {
+            return Ok(r);
+        }
+        if let Some(&r) = self.registers.get(name) {
+            return Ok(r);
+        }
+        let code = FIRST_NAMED_REGISTER + self.registers.len() as u32;
+        if code > LAST_NAMED_REGISTER {
+            return Err(format_err!("too many named registers"));
+        }
+        let r = char::from_u32(code).unwrap();
+        self.registers.insert(name.to_string(), r);
+        Ok(r)
+    }
+}
+
+fn is_identifier(token: &str) -> bool {
+    let mut chars = token.chars();
+    match chars.next() {
+        Some(c) if c.is_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_alphanumeric() || c == '_')
+        },
+        _ => false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{assemble,assemble_dialect,FIRST_NAMED_REGISTER};
+    use std::char;
+    use util::duet::Dialect;
+    use util::duet::Instruction::*;
+    use util::duet::Operand::*;
+
+    #[test]
+    fn labels_comments_and_constants() {
+        let source = "
+            # Count down from N.
+            .const N 10
+                    set a N
+            loop:   sub a 1     # decrement
+                    jnz a loop
+                    jgz 1 end
+            end:
+        ";
+        let assembly = assemble(source).unwrap();
+        assert_eq!(assembly.instructions,
+                   vec![Set(Register('a'), Value(10)),
+                        Sub(Register('a'), Value(1)),
+                        JumpNZ(Register('a'), Value(-1)),
+                        JumpGZ(Value(1), Value(1))]);
+    }
+
+    #[test]
+    fn named_registers() {
+        let assembly = assemble("set count 3\nadd total count\nsnd total")
+                           .unwrap();
+        let count = char::from_u32(FIRST_NAMED_REGISTER).unwrap();
+        let total = char::from_u32(FIRST_NAMED_REGISTER+1).unwrap();
+        assert_eq!(assembly.registers["count"], count);
+        assert_eq!(assembly.registers["total"], total);
+        assert_eq!(assembly.instructions,
+                   vec![Set(Register(count), Value(3)),
+                        Add(Register(total), Register(count)),
+                        Send(Register(total))]);
+    }
+
+    #[test]
+    fn named_registers_survive_disassembly() {
+        let source = "set count 3\n\
+                      loop: add total count\n\
+                      sub count 1\n\
+                      jnz count loop\n\
+                      snd total";
+        let assembly = assemble(source).unwrap();
+        let text = assembly.disassemble(true);
+        assert!(text.contains("add total count\n"), "{}", text);
+        let reassembled = assemble(&text).unwrap();
+        assert_eq!(reassembled.instructions, assembly.instructions);
+        assert_eq!(reassembled.registers, assembly.registers);
+        assert_eq!(reassembled.disassemble(true), text);
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        let error = |source| assemble(source).err().unwrap().to_string();
+        assert_eq!(error("set a 1\nfoo a"),
+                   "line 2: unrecognized instruction: foo");
+        assert_eq!(error("\nadd a"), "line 2: add takes 2 operand(s), found 1");
+        assert_eq!(error("set 1 2"),
+                   "line 1: set needs a register to write to");
+        assert_eq!(error("x: set a x"),
+                   "line 1: label x can only be a jump target");
+        assert_eq!(error("x:\nx:"), "line 2: x is already defined");
+    }
+
+    #[test]
+    fn extended_dialect() {
+        let source = "call f\nret\nf: push 3\npop a\ndiv a 2\nout a";
+        assert_eq!(assemble(source).err().unwrap().to_string(),
+                   "line 1: call is only available in the extended dialect");
+        let assembly = assemble_dialect(source, Dialect::Extended).unwrap();
+        assert_eq!(assembly.instructions,
+                   vec![Call(Value(2)),
+                        Return,
+                        Push(Value(3)),
+                        Pop(Register('a')),
+                        Divide(Register('a'), Value(2)),
+                        Out(Register('a'))]);
+    }
+}
@@ -0,0 +1,902 @@
+use std::cell::RefCell;
+use super::arith::{Arithmetic,ArithmeticErrorKind,Operation};
+use super::decode::{Arg,Op};
+
+// Which code Program::run executes. step() always interprets.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Backend {
+    Interpreter,
+    // Straight-line code compiled to closures, and simple counting loops
+    // computed in one go instead of iterated.
+    Compiled,
+}
+
+// One compiled instruction, working directly on the register file.
+type Action = Box<dyn Fn(&mut [isize], Arithmetic)
+                         -> Result<(), ArithmeticErrorKind>>;
+
+// How a block ends.
+enum Exit {
+    // Carry on with the instruction after the block.
+    Next,
+    // A jnz (or jgz, when `positive`) with a constant target in range.
+    Branch { condition: Arg, positive: bool, target: usize }
+}
+
+// A run of arithmetic instructions, plus the branch ending it, if any.
+struct Block {
+    actions: Vec<Action>,
+    exit: Exit,
+    // Number of instructions, counting the branch.
+    len: usize
+}
+
+// Why Compiled::run stopped.
+pub enum Stop {
+    // Out of budget, or off the end of the program.
+    Done,
+    // The instruction at pc has to go through the interpreter.
+    Interpret,
+    // The instruction at pc failed.
+    Error(ArithmeticErrorKind)
+}
+
+pub struct Compiled {
+    // Indexed by pc; only instructions that start a block have one.
+    blocks: Vec<Option<Block>>,
+    // Indexed by the pc of a loop's first instruction.
+    loops: Vec<Option<Loop>>
+}
+
+// Compile decoded instructions. Anything not compiled (snd, rcv, the
+// extended instructions and jumps by a register amount) is left to the
+// interpreter.
+pub fn compile(ops: &[Op]) -> Compiled {
+    let len = ops.len();
+    let mut leaders = vec![false; len + 1];
+    leaders[0] = true;
+    for (pc, &op) in ops.iter().enumerate() {
+        match branch(pc, op, len) {
+            Some((_, _, target)) => {
+                leaders[target] = true;
+                leaders[pc + 1] = true;
+            },
+            None if action(op).is_none() => leaders[pc + 1] = true,
+            None => {}
+        }
+    }
+
+    let mut blocks = Vec::with_capacity(len);
+    for start in 0..len {
+        blocks.push(if leaders[start] {
+            compile_block(ops, start, &leaders)
+        } else {
+            None
+        });
+    }
+    let mut loops: Vec<Option<Loop>> = (0..len).map(|_| None).collect();
+    for tail in 0..len {
+        if let Some(summary) = Loop::find(ops, tail) {
+            if loops[summary.head].is_none() {
+                let head = summary.head;
+                loops[head] = Some(summary);
+            }
+        }
+    }
+    Compiled { blocks: blocks, loops: loops }
+}
+
+fn compile_block(ops: &[Op], start: usize, leaders: &[bool]) -> Option<Block> {
+    let mut actions = Vec::new();
+    let mut pc = start;
+    while pc < ops.len() {
+        if let Some((condition, positive, target)) =
+                branch(pc, ops[pc], ops.len()) {
+            return Some(Block {
+                len: actions.len() + 1,
+                actions: actions,
+                exit: Exit::Branch {
+                    condition: condition,
+                    positive: positive,
+                    target: target
+                }
+            });
+        }
+        match action(ops[pc]) {
+            Some(action) => actions.push(action),
+            None => break
+        }
+        pc += 1;
+        if leaders[pc] {
+            break;
+        }
+    }
+    if actions.is_empty() {
+        return None;
+    }
+    Some(Block { len: actions.len(), actions: actions, exit: Exit::Next })
+}
+
+// A jump with a constant offset that stays in range, as (condition,
+// positive, target).
+fn branch(pc: usize, op: Op, len: usize) -> Option<(Arg, bool, usize)> {
+    let (condition, offset, positive) = match op {
+        Op::JumpNZ(condition, Arg::Value(offset)) => (condition, offset, false),
+        Op::JumpGZ(condition, Arg::Value(offset)) => (condition, offset, true),
+        _ => return None
+    };
+    let target = pc as isize + offset;
+    if target < 0 || target > len as isize {
+        // Out of range; let the interpreter report it.
+        return None;
+    }
+    Some((condition, positive, target as usize))
+}
+
+fn action(op: Op) -> Option<Action> {
+    Some(match op {
+        Op::Add(r, arg)      => arithmetic(Operation::Add, r, arg),
+        Op::Sub(r, arg)      => arithmetic(Operation::Sub, r, arg),
+        Op::Multiply(r, arg) => arithmetic(Operation::Multiply, r, arg),
+        Op::Mod(r, arg)      => arithmetic(Operation::Mod, r, arg),
+        Op::Set(r, Arg::Slot(s)) => {
+            Box::new(move |registers: &mut [isize], _| {
+                registers[r] = registers[s];
+                Ok(())
+            })
+        },
+        Op::Set(r, Arg::Value(v)) => {
+            Box::new(move |registers: &mut [isize], _| {
+                registers[r] = v;
+                Ok(())
+            })
+        },
+        _ => return None
+    })
+}
+
+fn arithmetic(operation: Operation, r: usize, arg: Arg) -> Action {
+    match arg {
+        Arg::Slot(s) => {
+            Box::new(move |registers: &mut [isize], policy: Arithmetic| {
+                registers[r] = policy.apply(operation, registers[r],
+                                            registers[s])?;
+                Ok(())
+            })
+        },
+        Arg::Value(v) => {
+            Box::new(move |registers: &mut [isize], policy: Arithmetic| {
+                registers[r] = policy.apply(operation, registers[r], v)?;
+                Ok(())
+            })
+        }
+    }
+}
+
+fn read(registers: &[isize], arg: Arg) -> isize {
+    match arg {
+        Arg::Slot(s) => registers[s],
+        Arg::Value(v) => v
+    }
+}
+
+impl Compiled {
+    // Run from `pc` until `budget` instructions have been executed, the
+    // program ends, or an instruction needs the interpreter. Returns the
+    // number of instructions executed.
+    pub fn run(&self, registers: &mut [isize], pc: &mut usize,
+               arithmetic: Arithmetic, budget: u64) -> (u64, Stop) {
+        let mut executed: u64 = 0;
+        // A loop that couldn't be summarized on entry is just run, without
+        // retrying the summary every time around.
+        let mut given_up: Option<&Loop> = None;
+        while *pc < self.blocks.len() {
+            if executed == budget {
+                return (executed, Stop::Done);
+            }
+            if let Some(summary) = given_up {
+                if *pc < summary.head || *pc > summary.tail {
+                    given_up = None;
+                }
+            }
+            if let (&Some(ref summary), None) = (&self.loops[*pc], given_up) {
+                match summary.run(registers, budget - executed) {
+                    Some(steps) => {
+                        executed += steps;
+                        *pc = summary.tail + 1;
+                        continue;
+                    },
+                    None => given_up = Some(summary)
+                }
+            }
+            let block = match self.blocks[*pc] {
+                Some(ref block) => block,
+                None => return (executed, Stop::Interpret)
+            };
+            if block.len as u64 > budget - executed {
+                return (executed, Stop::Interpret);
+            }
+            for (i, action) in block.actions.iter().enumerate() {
+                if let Err(kind) = action(registers, arithmetic) {
+                    *pc += i;
+                    return (executed + i as u64, Stop::Error(kind));
+                }
+            }
+            executed += block.len as u64;
+            *pc = match block.exit {
+                Exit::Branch { condition, positive, target } => {
+                    let value = read(registers, condition);
+                    if (positive && value > 0) || (!positive && value != 0) {
+                        target
+                    } else {
+                        *pc + block.len
+                    }
+                },
+                Exit::Next => *pc + block.len
+            };
+        }
+        (executed, Stop::Done)
+    }
+}
+
+// A value during one iteration of a loop, as a function of the iteration
+// number k: base + step * k.
+#[derive(Clone,Copy,Debug,PartialEq)]
+struct Affine {
+    base: isize,
+    step: isize
+}
+
+impl Affine {
+    fn constant(value: isize) -> Self {
+        Affine { base: value, step: 0 }
+    }
+
+    fn at(&self, k: isize) -> Option<isize> {
+        self.step.checked_mul(k).and_then(|v| v.checked_add(self.base))
+    }
+
+    fn apply(&self, operation: Operation, other: Affine) -> Option<Affine> {
+        let (base, step) = match operation {
+            Operation::Add => (self.base.checked_add(other.base)?,
+                               self.step.checked_add(other.step)?),
+            Operation::Sub => (self.base.checked_sub(other.base)?,
+                               self.step.checked_sub(other.step)?),
+            Operation::Multiply if other.step == 0 => {
+                (self.base.checked_mul(other.base)?,
+                 self.step.checked_mul(other.base)?)
+            },
+            Operation::Multiply if self.step == 0 => {
+                (self.base.checked_mul(other.base)?,
+                 self.base.checked_mul(other.step)?)
+            },
+            Operation::Mod if self.step == 0 && other.step == 0 => {
+                (Arithmetic::Checked.apply(operation, self.base, other.base)
+                                    .ok()?, 0)
+            },
+            _ => return None
+        };
+        Some(Affine { base: base, step: step })
+    }
+
+    // How many k in 0..n make a jnz (or jgz) on this value fall through.
+    fn falls_through(&self, positive: bool, n: isize) -> Option<isize> {
+        if self.step == 0 {
+            let falls = if positive { self.base <= 0 } else { self.base == 0 };
+            return Some(if falls { n } else { 0 });
+        }
+        let neg_base = self.base.checked_neg()?;
+        if !positive {
+            return Some(match exact_div(neg_base, self.step) {
+                Some(k) if k >= 0 && k < n => 1,
+                _ => 0
+            });
+        }
+        // base + step * k <= 0
+        if self.step > 0 {
+            let last = floor_div(neg_base, self.step)?;
+            Some(clamp(last.checked_add(1)?, 0, n))
+        } else {
+            let first = ceil_div(self.base, self.step.checked_neg()?)?;
+            Some(n - clamp(first, 0, n))
+        }
+    }
+}
+
+// a / b, if b divides a exactly. Loops mostly count in ones, and division
+// is slow enough to be worth avoiding then.
+fn exact_div(a: isize, b: isize) -> Option<isize> {
+    match b {
+        1 => Some(a),
+        -1 => a.checked_neg(),
+        _ if a % b == 0 => Some(a / b),
+        _ => None
+    }
+}
+
+// These and everything computed from them return None on overflow, which
+// makes the loop fall back to being stepped.
+fn floor_div(a: isize, b: isize) -> Option<isize> {
+    let q = a.checked_div(b)?;
+    if (a % b != 0) && ((a < 0) != (b < 0)) {
+        q.checked_sub(1)
+    } else {
+        Some(q)
+    }
+}
+
+fn ceil_div(a: isize, b: isize) -> Option<isize> {
+    floor_div(a.checked_neg()?, b)?.checked_neg()
+}
+
+fn clamp(v: isize, low: isize, high: isize) -> isize {
+    if v < low { low } else if v > high { high } else { v }
+}
+
+// A region of "set register value" instructions inside a loop, skipped by
+// the branch just before it.
+struct Guard {
+    // Index of the branch in the loop body.
+    at: usize,
+    condition: Arg,
+    positive: bool,
+    sets: Vec<(usize, isize)>
+}
+
+// An innermost loop, from `head` to a backward branch at `tail`, whose
+// iteration count can be worked out on entry. That needs every register
+// written in the loop to be one of:
+//
+//   - a counter, only changed by adding or subtracting constants;
+//   - a temporary, always set before it's read in each iteration;
+//   - a flag, only set to constants inside guarded regions and never read.
+//
+// Every other value is then an affine function of the iteration number, so
+// the exit branch says exactly when the loop ends, and each guard says
+// whether (and how often) its region runs.
+struct Loop {
+    head: usize,
+    tail: usize,
+    // The register slot for each register the loop uses. Everything below
+    // refers to registers by their index in here.
+    slots: Vec<usize>,
+    body: Vec<Op>,
+    // For each counter, its register and how much it changes per iteration.
+    counters: Vec<(usize, isize)>,
+    guards: Vec<Guard>,
+    scratch: RefCell<Scratch>
+}
+
+// Space for run() to work in, kept between calls to save allocating it on
+// every one.
+struct Scratch {
+    // By register.
+    values: Vec<Affine>,
+    // By position in the loop body.
+    computed: Vec<Affine>,
+    // By guard.
+    conditions: Vec<Affine>
+}
+
+impl Loop {
+    fn find(ops: &[Op], tail: usize) -> Option<Loop> {
+        let (_, _, head) = match ops[tail] {
+            Op::JumpNZ(_, Arg::Value(offset)) |
+            Op::JumpGZ(_, Arg::Value(offset)) if offset <= 0 => {
+                branch(tail, ops[tail], ops.len())?
+            },
+            _ => return None
+        };
+        let body = ops[head..tail+1].to_vec();
+
+        // Calls, returns and computed jumps could land anywhere; otherwise
+        // the only way into the loop must be through its head.
+        for (pc, &op) in ops.iter().enumerate() {
+            match op {
+                Op::Call(_) | Op::Return |
+                Op::JumpNZ(_, Arg::Slot(_)) | Op::JumpGZ(_, Arg::Slot(_)) => {
+                    return None;
+                },
+                _ => {}
+            }
+            if pc >= head && pc <= tail {
+                continue;
+            }
+            if let Some((_, _, target)) = branch(pc, op, ops.len()) {
+                if target > head && target <= tail {
+                    return None;
+                }
+            }
+        }
+
+        // Find the guarded regions; everything else must be arithmetic.
+        let mut guards = Vec::new();
+        let mut guarded = vec![false; body.len()];
+        let mut i = 0;
+        while i + 1 < body.len() {
+            if let Some((condition, positive, target)) =
+                    branch(i, body[i], body.len()) {
+                if target <= i || target >= body.len() {
+                    return None;
+                }
+                let mut sets = Vec::new();
+                for j in i+1..target {
+                    match body[j] {
+                        Op::Set(r, Arg::Value(v)) => sets.push((r, v)),
+                        _ => return None
+                    }
+                    guarded[j] = true;
+                }
+                guards.push(Guard {
+                    at: i,
+                    condition: condition,
+                    positive: positive,
+                    sets: sets
+                });
+                i = target;
+                continue;
+            }
+            match body[i] {
+                Op::Add(_, _) | Op::Sub(_, _) | Op::Multiply(_, _) |
+                Op::Mod(_, _) | Op::Set(_, _) => {},
+                _ => return None
+            }
+            i += 1;
+        }
+
+        // Classify the registers.
+        let mut flags: Vec<usize> = Vec::new();
+        for guard in &guards {
+            for &(r, _) in &guard.sets {
+                if flags.contains(&r) {
+                    return None;
+                }
+                flags.push(r);
+            }
+        }
+        let mut counters: Vec<(usize, isize)> = Vec::new();
+        let mut temporaries: Vec<usize> = Vec::new();
+        let mut read_before_set: Vec<usize> = Vec::new();
+        for (j, &op) in body.iter().enumerate() {
+            if guarded[j] {
+                continue;
+            }
+            let (reads, writes) = accesses(op);
+            for &r in &reads {
+                if flags.contains(&r) {
+                    return None;
+                }
+                if !temporaries.contains(&r) {
+                    read_before_set.push(r);
+                }
+            }
+            let r = match writes {
+                Some(r) => r,
+                None => continue
+            };
+            if flags.contains(&r) {
+                return None;
+            }
+            match op {
+                Op::Add(_, Arg::Value(v)) | Op::Sub(_, Arg::Value(v))
+                        if !temporaries.contains(&r) => {
+                    let v = if let Op::Sub(_, _) = op { v.checked_neg()? }
+                            else { v };
+                    match counters.iter().position(|&(c, _)| c == r) {
+                        Some(index) => {
+                            counters[index].1 =
+                                counters[index].1.checked_add(v)?;
+                        },
+                        None => counters.push((r, v))
+                    }
+                },
+                Op::Set(_, _) if !read_before_set.contains(&r) &&
+                                 !counters.iter().any(|&(c, _)| c == r) => {
+                    if !temporaries.contains(&r) {
+                        temporaries.push(r);
+                    }
+                },
+                Op::Set(_, _) | Op::Add(_, _) | Op::Sub(_, _) |
+                Op::Multiply(_, _) | Op::Mod(_, _)
+                        if temporaries.contains(&r) => {},
+                _ => return None
+            }
+        }
+
+        // Renumber the loop's registers from 0, so run() can work on a
+        // small array instead of the whole register file.
+        let mut slots: Vec<usize> = Vec::new();
+        let body = body.into_iter()
+                       .map(|op| relabel(op, &mut slots))
+                       .collect::<Vec<Op>>();
+        for guard in &mut guards {
+            guard.condition = relabel_arg(guard.condition, &mut slots);
+            for set in &mut guard.sets {
+                set.0 = local(set.0, &mut slots);
+            }
+        }
+        for counter in &mut counters {
+            counter.0 = local(counter.0, &mut slots);
+        }
+        if guards.len() >= 64 {
+            return None;
+        }
+        let scratch = Scratch {
+            values: vec![Affine::constant(0); slots.len()],
+            computed: vec![Affine::constant(0); body.len()],
+            conditions: vec![Affine::constant(0); guards.len()]
+        };
+
+        Some(Loop {
+            head: head,
+            tail: tail,
+            slots: slots,
+            body: body,
+            counters: counters,
+            guards: guards,
+            scratch: RefCell::new(scratch)
+        })
+    }
+
+    // Run the whole loop at once, if it can be done in no more than
+    // `budget` steps without any arithmetic overflowing. Returns the number
+    // of steps it took.
+    fn run(&self, registers: &mut [isize], budget: u64) -> Option<u64> {
+        let mut scratch = self.scratch.borrow_mut();
+        let Scratch { ref mut values, ref mut computed, ref mut conditions } =
+            *scratch;
+        for (r, &slot) in self.slots.iter().enumerate() {
+            values[r] = Affine::constant(registers[slot]);
+        }
+        for &(r, step) in &self.counters {
+            values[r].step = step;
+        }
+
+        // The loop ends on the first iteration where the exit branch falls
+        // through; it must do so exactly once.
+        let exit = self.iterate(values, computed, conditions)?;
+        let iterations = if exit.step == 0 {
+            if exit.falls_through(self.positive(), 1)? == 1 {
+                1
+            } else {
+                return None;
+            }
+        } else if self.positive() {
+            if exit.base <= 0 {
+                1
+            } else if exit.step < 0 {
+                ceil_div(exit.base, exit.step.checked_neg()?)?.checked_add(1)?
+            } else {
+                return None;
+            }
+        } else {
+            match exact_div(exit.base.checked_neg()?, exit.step) {
+                Some(k) if k >= 0 => k.checked_add(1)?,
+                _ => return None
+            }
+        };
+        let last = iterations - 1;
+
+        // Check nothing overflows, and count how often each guarded region
+        // runs. Checking the first and last iterations covers everything in
+        // between, since affine values are monotonic.
+        for value in computed.iter() {
+            value.at(last)?;
+        }
+        exit.at(last)?;
+        let mut steps = (self.body.len() as isize).checked_mul(iterations)?;
+        let mut fired: u64 = 0;
+        for (i, guard) in self.guards.iter().enumerate() {
+            let runs = conditions[i].falls_through(guard.positive,
+                                                   iterations)?;
+            steps = steps.checked_sub((guard.sets.len() as isize)
+                                          .checked_mul(iterations - runs)?)?;
+            if runs > 0 {
+                fired |= 1 << i;
+            }
+        }
+        if steps as u64 > budget {
+            return None;
+        }
+
+        for (r, &slot) in self.slots.iter().enumerate() {
+            // Checked above, so this can't fail halfway.
+            registers[slot] = values[r].at(last)?;
+        }
+        for (i, guard) in self.guards.iter().enumerate() {
+            if fired & (1 << i) != 0 {
+                for &(r, v) in &guard.sets {
+                    registers[self.slots[r]] = v;
+                }
+            }
+        }
+        Some(steps as u64)
+    }
+
+    // Whether the exit branch is a jgz rather than a jnz.
+    fn positive(&self) -> bool {
+        match self.body[self.body.len() - 1] {
+            Op::JumpGZ(_, _) => true,
+            _ => false
+        }
+    }
+
+    // Work out one iteration of the body, with every value an affine
+    // function of the iteration number. Each value computed is stored in
+    // `computed` (at the index of the instruction computing it), and each
+    // guard's condition in `conditions`. Returns the exit branch's condition.
+    //
+    // Every call writes the same entries of `computed`, and the rest stay
+    // zero, so old values from earlier calls never get checked.
+    fn iterate(&self, values: &mut [Affine], computed: &mut [Affine],
+               conditions: &mut [Affine]) -> Option<Affine> {
+        let mut next_guard = 0;
+        let mut j = 0;
+        while j + 1 < self.body.len() {
+            if next_guard < self.guards.len() &&
+               self.guards[next_guard].at == j {
+                let guard = &self.guards[next_guard];
+                conditions[next_guard] = affine(values, guard.condition);
+                j += guard.sets.len() + 1;
+                next_guard += 1;
+                continue;
+            }
+            let (r, operation, arg) = match self.body[j] {
+                Op::Set(r, arg) => {
+                    values[r] = affine(values, arg);
+                    computed[j] = values[r];
+                    j += 1;
+                    continue;
+                },
+                Op::Add(r, arg)      => (r, Operation::Add, arg),
+                Op::Sub(r, arg)      => (r, Operation::Sub, arg),
+                Op::Multiply(r, arg) => (r, Operation::Multiply, arg),
+                Op::Mod(r, arg)      => (r, Operation::Mod, arg),
+                _ => unreachable!()
+            };
+            let value = values[r].apply(operation, affine(values, arg))?;
+            computed[j] = value;
+            values[r] = value;
+            j += 1;
+        }
+        match self.body[self.body.len() - 1] {
+            Op::JumpNZ(condition, _) | Op::JumpGZ(condition, _) => {
+                Some(affine(values, condition))
+            },
+            _ => unreachable!()
+        }
+    }
+}
+
+// The loop-local number for register slot `r`, allocating one if needed.
+fn local(r: usize, slots: &mut Vec<usize>) -> usize {
+    match slots.iter().position(|&s| s == r) {
+        Some(index) => index,
+        None => {
+            slots.push(r);
+            slots.len() - 1
+        }
+    }
+}
+
+fn relabel_arg(arg: Arg, slots: &mut Vec<usize>) -> Arg {
+    match arg {
+        Arg::Slot(r) => Arg::Slot(local(r, slots)),
+        value => value
+    }
+}
+
+fn relabel(op: Op, slots: &mut Vec<usize>) -> Op {
+    match op {
+        Op::Set(r, arg) => {
+            let r = local(r, slots);
+            Op::Set(r, relabel_arg(arg, slots))
+        },
+        Op::Add(r, arg) => {
+            let r = local(r, slots);
+            Op::Add(r, relabel_arg(arg, slots))
+        },
+        Op::Sub(r, arg) => {
+            let r = local(r, slots);
+            Op::Sub(r, relabel_arg(arg, slots))
+        },
+        Op::Multiply(r, arg) => {
+            let r = local(r, slots);
+            Op::Multiply(r, relabel_arg(arg, slots))
+        },
+        Op::Mod(r, arg) => {
+            let r = local(r, slots);
+            Op::Mod(r, relabel_arg(arg, slots))
+        },
+        Op::JumpNZ(condition, offset) => {
+            Op::JumpNZ(relabel_arg(condition, slots), offset)
+        },
+        Op::JumpGZ(condition, offset) => {
+            Op::JumpGZ(relabel_arg(condition, slots), offset)
+        },
+        op => op
+    }
+}
+
+fn affine(values: &[Affine], arg: Arg) -> Affine {
+    match arg {
+        Arg::Slot(s) => values[s],
+        Arg::Value(v) => Affine::constant(v)
+    }
+}
+
+// The registers an arithmetic instruction or branch reads, and the one it
+// writes.
+fn accesses(op: Op) -> (Vec<usize>, Option<usize>) {
+    let slots = |args: &[Arg]| {
+        args.iter()
+            .filter_map(|&arg| match arg {
+                Arg::Slot(s) => Some(s),
+                Arg::Value(_) => None
+            })
+            .collect::<Vec<usize>>()
+    };
+    match op {
+        Op::Set(r, arg) => (slots(&[arg]), Some(r)),
+        Op::Add(r, arg) | Op::Sub(r, arg) |
+        Op::Multiply(r, arg) | Op::Mod(r, arg) => {
+            (slots(&[Arg::Slot(r), arg]), Some(r))
+        },
+        Op::JumpNZ(condition, offset) | Op::JumpGZ(condition, offset) => {
+            (slots(&[condition, offset]), None)
+        },
+        _ => (Vec::new(), None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Backend;
+    use util::duet::{parse_instructions,Arithmetic,Instruction,Program};
+    use util::duet::asm::assemble;
+
+    // Run on both backends, in chunks of `chunk` steps, and check they
+    // agree at the end of every chunk. Programs still going after 20000
+    // chunks are only compared that far.
+    fn compare(instructions: &Vec<Instruction>, setup: &[(char, isize)],
+               arithmetic: Arithmetic, chunk: u64) {
+        let chunks = 20_000;
+        let mut reference = Program::new(0, instructions);
+        let mut compiled = Program::new(0, instructions);
+        compiled.set_backend(Backend::Compiled);
+        for program in vec![&mut reference, &mut compiled] {
+            program.set_arithmetic(arithmetic);
+            for &(r, v) in setup {
+                program.store(r, v);
+            }
+        }
+        for _ in 0..chunks {
+            let expected = reference.run(chunk).map_err(|e| e.to_string());
+            let actual = compiled.run(chunk).map_err(|e| e.to_string());
+            assert_eq!(actual, expected);
+            assert_eq!(compiled.register_values(),
+                       reference.register_values());
+            assert_eq!(compiled.next_instruction(),
+                       reference.next_instruction());
+            assert_eq!(compiled.steps(), reference.steps());
+            if expected.is_err() || !reference.is_running() {
+                return;
+            }
+        }
+    }
+
+    fn source(text: &str) -> Vec<Instruction> {
+        assemble(text).unwrap().instructions
+    }
+
+    #[test]
+    fn puzzle_inputs_match_the_interpreter() {
+        let original = parse_instructions("input/day23_orig.txt").unwrap();
+        let optimized = parse_instructions("input/day23_opt.txt").unwrap();
+        for &chunk in &[1, 7, 1000, u64::max_value()] {
+            compare(&original, &[], Arithmetic::Checked, chunk);
+            compare(&optimized, &[], Arithmetic::Checked, chunk);
+        }
+    }
+
+    #[test]
+    fn summarized_loops_match_the_interpreter() {
+        // Counts the divisors of n below it; the inner loop gets summarized,
+        // and its guard fires at most once per pass.
+        let divisors = source("
+                    set d 2
+            outer:  set e 2
+                    set f 1
+            inner:  set g d
+                    mul g e
+                    sub g n
+                    jnz g skip
+                    set f 0
+            skip:   sub e -1
+                    set g e
+                    sub g n
+                    jnz g inner
+                    jnz f prime
+                    add count 1
+            prime:  add d 1
+                    set g d
+                    sub g n
+                    jnz g outer
+        ");
+        for &chunk in &[1, 5, 50, u64::max_value()] {
+            compare(&divisors, &[('n', 60)], Arithmetic::Checked, chunk);
+            compare(&divisors, &[('n', 61)], Arithmetic::Checked, chunk);
+        }
+
+        // Counting down with jgz, and a guard that fires on several
+        // iterations.
+        let countdown = source("
+            loop:   sub a 3
+                    add b 2
+                    set c a
+                    sub c 10
+                    jgz c skip
+                    set flag 7
+            skip:   set c a
+                    jgz c loop
+        ");
+        for &a in &[0, 1, 29, 30, 31, 100] {
+            compare(&countdown, &[('a', a)], Arithmetic::Checked, 1);
+            compare(&countdown, &[('a', a)], Arithmetic::Checked,
+                    u64::max_value());
+        }
+    }
+
+    #[test]
+    fn counting_loops_are_not_iterated() {
+        let instructions = source("
+                    set a 1000000000000
+            loop:   add b 3
+                    sub a 1
+                    jnz a loop
+        ");
+        let mut program = Program::new(0, &instructions);
+        program.set_backend(Backend::Compiled);
+        assert_eq!(program.run(u64::max_value()).unwrap(),
+                   (3_000_000_000_001, None));
+        assert_eq!(program.read_register('b'), 3_000_000_000_000);
+    }
+
+    #[test]
+    fn unsummarizable_loops_still_run() {
+        // Not affine (a is squared), and a loop whose exit is never taken
+        // exactly (jnz on a value that skips over zero).
+        let squares = source("
+            loop:   set b a
+                    mul b a
+                    add sum b
+                    sub a 1
+                    jnz a loop
+        ");
+        compare(&squares, &[('a', 20)], Arithmetic::Checked, 3);
+        compare(&squares, &[('a', 20)], Arithmetic::Checked,
+                u64::max_value());
+        let overshoot = source("
+            loop:   sub a 2
+                    jnz a loop
+        ");
+        compare(&overshoot, &[('a', 7)], Arithmetic::Wrapping, 1000);
+    }
+
+    #[test]
+    fn errors_match_the_interpreter() {
+        let overflow = source("
+                    set a 1
+            loop:   mul a 3
+                    add n 1
+                    jgz a loop
+        ");
+        for &arithmetic in &[Arithmetic::Checked, Arithmetic::Wrapping,
+                             Arithmetic::Saturating] {
+            compare(&overflow, &[], arithmetic, 10);
+        }
+        let by_zero = source("set a 5\nsub b 1\nmod a b\njnz b -2\nrcv a");
+        compare(&by_zero, &[('b', 3)], Arithmetic::Checked,
+                u64::max_value());
+    }
+}
@@ -0,0 +1,157 @@
+use std::collections::{BTreeMap,BTreeSet,HashMap};
+use super::{Instruction,Operand};
+
+// Turn instructions back into duet source, one instruction per line, which
+// assembles back into the same instructions.
+//
+// `registers` maps multi-character register names to their characters, as in
+// asm::Assembly; any other allocated registers print as reg0, reg1, ...
+// Assembly::disassemble passes an assembly's own names.
+//
+// With `labels`, every constant jump or call that lands inside the program
+// (or just past its end) gets a label, L0, L1, ... in address order, and the
+// jump refers to that label instead of an offset. Instructions are then
+// indented so the labels stand out.
+pub fn disassemble(instructions: &[Instruction],
+                   registers: &BTreeMap<String, char>,
+                   labels: bool) -> String {
+    let names: HashMap<char, &str> = registers.iter()
+                                              .map(|(name, &r)| (r, &name[..]))
+                                              .collect();
+    let mut targets: BTreeSet<usize> = BTreeSet::new();
+    if labels {
+        for (i, inst) in instructions.iter().enumerate() {
+            if let Some(target) = jump_target(i, inst, instructions.len()) {
+                targets.insert(target);
+            }
+        }
+    }
+    let mut label_names: HashMap<usize, String> = HashMap::new();
+    let mut next_label: usize = 0;
+    for target in targets {
+        let mut name = format!("L{}", next_label);
+        while registers.contains_key(&name) {
+            next_label += 1;
+            name = format!("L{}", next_label);
+        }
+        next_label += 1;
+        label_names.insert(target, name);
+    }
+
+    let mut result = String::new();
+    for (i, inst) in instructions.iter().enumerate() {
+        if let Some(label) = label_names.get(&i) {
+            result.push_str(&format!("{}:\n", label));
+        }
+        if labels {
+            result.push_str("    ");
+        }
+        result.push_str(inst.mnemonic());
+        let target = jump_target(i, inst, instructions.len());
+        let operands = inst.operands();
+        for (j, op) in operands.iter().cloned().enumerate() {
+            result.push(' ');
+            match target {
+                Some(target) if j == operands.len() - 1 &&
+                                label_names.contains_key(&target) => {
+                    result.push_str(&label_names[&target]);
+                },
+                _ => match op {
+                    Operand::Register(r) if names.contains_key(&r) => {
+                        result.push_str(names[&r]);
+                    },
+                    _ => result.push_str(&op.to_string())
+                }
+            }
+        }
+        result.push('\n');
+    }
+    if let Some(label) = label_names.get(&instructions.len()) {
+        result.push_str(&format!("{}:\n", label));
+    }
+    result
+}
+
+// Where a jump or call with a constant offset lands, if that's within the
+// program or just past its end.
+fn jump_target(pc: usize, inst: &Instruction, len: usize) -> Option<usize> {
+    match *inst {
+        Instruction::JumpGZ(_, Operand::Value(offset)) |
+        Instruction::JumpNZ(_, Operand::Value(offset)) |
+        Instruction::Call(Operand::Value(offset)) => {
+            let target = pc as isize + offset;
+            if target >= 0 && target <= len as isize {
+                Some(target as usize)
+            } else {
+                None
+            }
+        },
+        _ => None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::disassemble;
+    use std::collections::BTreeMap;
+    use std::fs::File;
+    use std::io::Read;
+    use util::duet::asm::assemble;
+    use util::duet::Instruction::*;
+    use util::duet::Operand::*;
+
+    fn read(file: &str) -> String {
+        let mut source = String::new();
+        File::open(file).unwrap().read_to_string(&mut source).unwrap();
+        source
+    }
+
+    #[test]
+    fn display_matches_source_syntax() {
+        assert_eq!(JumpGZ(Register('a'), Value(-2)).to_string(), "jgz a -2");
+        assert_eq!(Receive(Register('b')).to_string(), "rcv b");
+    }
+
+    #[test]
+    fn puzzle_inputs_print_unchanged() {
+        let no_names = BTreeMap::new();
+        for file in &["input/day18.txt", "input/day23_orig.txt"] {
+            let source = read(file);
+            let instructions = assemble(&source).unwrap().instructions;
+            assert_eq!(disassemble(&instructions, &no_names, false), source);
+        }
+    }
+
+    #[test]
+    fn labeled_output_round_trips() {
+        for file in &["input/day18.txt", "input/day23_orig.txt",
+                      "input/day23_opt.txt"] {
+            let assembly = assemble(&read(file)).unwrap();
+            let text = disassemble(&assembly.instructions, &assembly.registers,
+                                   true);
+            assert_eq!(assemble(&text).unwrap().instructions,
+                       assembly.instructions);
+        }
+    }
+
+    #[test]
+    fn labels_and_names_are_synthesized() {
+        let assembly = assemble("
+            set count 3
+            top: sub count 1
+            jnz count top
+            jgz 1 done
+            done:
+        ").unwrap();
+        assert_eq!(disassemble(&assembly.instructions, &assembly.registers,
+                               true),
+                   "    set count 3\n\
+                    L0:\n    sub count 1\n\
+                    \x20   jnz count L0\n\
+                    \x20   jgz 1 L1\n\
+                    L1:\n");
+        assert_eq!(disassemble(&assembly.instructions, &BTreeMap::new(),
+                               false),
+                   "set reg0 3\nsub reg0 1\njnz reg0 -1\njgz 1 1\n");
+    }
+}
@@ -0,0 +1,409 @@
+use std::collections::VecDeque;
+use super::{Instruction,Io,Operand,Receive};
+use super::Instruction::*;
+use super::Operand::*;
+
+// Registers random code computes with. Loop counters and the temporary used
+// by counting-up loops are kept separate, so loops always terminate.
+const DATA: [char; 6] = ['a', 'b', 'c', 'd', 'e', 'f'];
+const COUNTERS: [char; 2] = ['x', 'y'];
+const TEMPORARY: char = 't';
+
+// Values fed to "rcv"; once they run out the program blocks, which ends it
+// as far as the tests are concerned.
+pub const INPUT: [isize; 3] = [3, -2, 7];
+
+// A small xorshift generator, so that any failure can be reproduced from
+// its seed without depending on a random number crate.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // Zero is a fixed point of xorshift.
+        let mut rng = Rng(seed.wrapping_mul(0x9E3779B97F4A7C15) | 1);
+        rng.next();
+        rng
+    }
+
+    pub fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    pub fn range(&mut self, low: isize, high: isize) -> isize {
+        low + self.below((high - low + 1) as usize) as isize
+    }
+
+    pub fn chance(&mut self, percent: usize) -> bool {
+        self.below(100) < percent
+    }
+}
+
+// Generate a random program that always terminates: jumps only go forward,
+// except at the end of loops whose counters nothing else touches. It may
+// still stop early with an error (overflow, say, or mod by zero), or by
+// blocking on "rcv" once INPUT runs out.
+pub fn random_program(rng: &mut Rng) -> Vec<Instruction> {
+    let mut instructions = Vec::new();
+    for &r in &DATA {
+        if rng.chance(50) {
+            instructions.push(Set(Register(r), Value(rng.range(-10, 10))));
+        }
+    }
+    let length = rng.range(3, 8) as usize;
+    block(rng, 0, length, false, &mut instructions);
+    instructions
+}
+
+// Append `length` random statements. Loop bodies are sometimes `simple`:
+// just the kind of code the compiled backend can summarize.
+fn block(rng: &mut Rng, depth: usize, length: usize, simple: bool,
+         out: &mut Vec<Instruction>) {
+    for _ in 0..length {
+        let choice = rng.below(100);
+        if choice < 12 && depth < COUNTERS.len() {
+            counted_loop(rng, depth, out);
+        } else if choice < 22 {
+            // Skip over a few statements, sometimes just a flag being set.
+            let mut skipped = Vec::new();
+            if rng.chance(50) {
+                skipped.push(Set(Register(register(rng)),
+                                 Value(rng.range(-2, 2))));
+            } else {
+                let length = rng.range(1, 3) as usize;
+                block(rng, depth, length, simple, &mut skipped);
+            }
+            let condition = operand(rng);
+            let offset = Value(skipped.len() as isize + 1);
+            out.push(if rng.chance(50) {
+                JumpNZ(condition, offset)
+            } else {
+                JumpGZ(condition, offset)
+            });
+            out.extend(skipped);
+        } else if !simple && choice < 28 {
+            out.push(Send(operand(rng)));
+        } else if !simple && choice < 31 {
+            out.push(Receive(Register(register(rng))));
+        } else {
+            out.push(arithmetic(rng, simple));
+        }
+    }
+}
+
+fn counted_loop(rng: &mut Rng, depth: usize, out: &mut Vec<Instruction>) {
+    let counter = Register(COUNTERS[depth]);
+    let simple = rng.chance(50);
+    let mut body = Vec::new();
+    let length = rng.range(1, 4) as usize;
+    block(rng, depth + 1, length, simple, &mut body);
+    let times = rng.range(1, 6);
+    if rng.chance(50) {
+        // Count down with jgz.
+        out.push(Set(counter, Value(times)));
+        let back = -(body.len() as isize + 1);
+        out.extend(body);
+        out.push(Sub(counter, Value(1)));
+        out.push(JumpGZ(counter, Value(back)));
+    } else {
+        // Count up with jnz, the way the puzzle input does.
+        out.push(Set(counter, Value(0)));
+        let back = -(body.len() as isize + 3);
+        out.extend(body);
+        out.push(Add(counter, Value(1)));
+        out.push(Set(Register(TEMPORARY), counter));
+        out.push(Sub(Register(TEMPORARY), Value(times)));
+        out.push(JumpNZ(Register(TEMPORARY), Value(back)));
+    }
+}
+
+fn arithmetic(rng: &mut Rng, simple: bool) -> Instruction {
+    let r = Register(register(rng));
+    if simple {
+        return match rng.below(4) {
+            0 => Set(r, operand(rng)),
+            1 => Add(r, Value(rng.range(-3, 3))),
+            2 => Sub(r, Value(rng.range(-3, 3))),
+            _ => Multiply(r, Value(rng.range(-3, 3)))
+        };
+    }
+    match rng.below(5) {
+        0 => Set(r, operand(rng)),
+        1 => Add(r, operand(rng)),
+        2 => Sub(r, operand(rng)),
+        3 => Multiply(r, operand(rng)),
+        // Mostly by a constant, which can't be zero.
+        _ if rng.chance(75) => Mod(r, Value(rng.range(1, 7))),
+        _ => Mod(r, operand(rng))
+    }
+}
+
+fn register(rng: &mut Rng) -> char {
+    DATA[rng.below(DATA.len())]
+}
+
+// Any register may be read, including loop counters and p.
+fn operand(rng: &mut Rng) -> Operand {
+    match rng.below(10) {
+        0..=3 => Value(rng.range(-5, 5)),
+        4 => Register(COUNTERS[rng.below(COUNTERS.len())]),
+        5 => Register('p'),
+        _ => Register(register(rng))
+    }
+}
+
+// Io for fuzzing: "rcv" reads from INPUT and "snd" is recorded.
+#[derive(Clone,Debug,PartialEq)]
+pub struct Recorder {
+    pub input: VecDeque<isize>,
+    pub output: Vec<isize>
+}
+
+impl Default for Recorder {
+    fn default() -> Self {
+        Recorder {
+            input: INPUT.iter().cloned().collect(),
+            output: Vec::new()
+        }
+    }
+}
+
+impl Io for Recorder {
+    fn send(&mut self, value: isize) {
+        self.output.push(value);
+    }
+
+    fn receive(&mut self, _current: isize) -> Receive {
+        match self.input.pop_front() {
+            Some(v) => Receive::Store(v),
+            None => Receive::Block
+        }
+    }
+
+    fn state(&self) -> Vec<isize> {
+        self.input.iter().chain(self.output.iter()).cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{random_program,Recorder,Rng};
+    use std::collections::BTreeMap;
+    use util::duet::{Arithmetic,ArithmeticError,Backend,Instruction,Operand,
+                     Program};
+    use util::duet::Instruction::*;
+    use util::duet::Operand::*;
+    use util::duet::analyze::analyze;
+    use util::duet::asm::assemble;
+    use util::duet::disasm::disassemble;
+
+    // Where a program got to, and everything it did on the way. Arithmetic
+    // errors are kept as their kind and pc, since their messages quote the
+    // instruction, which a rewrite may have changed.
+    #[derive(Debug,PartialEq)]
+    struct Outcome {
+        registers: Vec<(char, isize)>,
+        output: Vec<isize>,
+        pc: usize,
+        steps: u64,
+        error: Option<String>
+    }
+
+    const MAX_STEPS: u64 = 1_000_000;
+
+    // How a program is run: by step(), or by run() on a backend, in chunks
+    // of the given size.
+    #[derive(Clone,Copy,Debug)]
+    enum Runner {
+        Step,
+        Run(Backend, u64)
+    }
+
+    fn execute(instructions: &Vec<Instruction>, runner: Runner,
+               arithmetic: Arithmetic) -> Outcome {
+        let mut program = Program::with_io(0, instructions,
+                                           Recorder::default());
+        program.set_arithmetic(arithmetic);
+        let mut error = None;
+        match runner {
+            Runner::Step => {
+                while program.is_running() && program.steps() < MAX_STEPS {
+                    if let Err(e) = program.step() {
+                        error = Some(describe(e));
+                        break;
+                    }
+                }
+            },
+            Runner::Run(backend, chunk) => {
+                program.set_backend(backend);
+                while program.is_running() && program.steps() < MAX_STEPS {
+                    let chunk = chunk.min(MAX_STEPS - program.steps());
+                    if let Err(e) = program.run(chunk) {
+                        error = Some(describe(e));
+                        break;
+                    }
+                }
+            }
+        }
+        Outcome {
+            registers: program.register_values(),
+            output: program.io().output.clone(),
+            pc: program.pc,
+            steps: program.steps(),
+            error: error
+        }
+    }
+
+    fn describe(error: ::failure::Error) -> String {
+        match error.downcast_ref::<ArithmeticError>() {
+            Some(e) => format!("{:?} at pc {}", e.kind, e.pc),
+            None => error.to_string()
+        }
+    }
+
+    type Pass = fn(&Vec<Instruction>, Arithmetic) -> Vec<Instruction>;
+
+    // Rewrites that must not change what a program does. Both of the
+    // analysis-based passes keep every instruction where it is, so the
+    // program takes the same steps; what they check is that the interval
+    // analysis never claims something that isn't so.
+    fn passes() -> Vec<(&'static str, Pass)> {
+        fn round_trip(instructions: &Vec<Instruction>, _: Arithmetic)
+                -> Vec<Instruction> {
+            let text = disassemble(instructions, &BTreeMap::new(), true);
+            assemble(&text).unwrap().instructions
+        }
+
+        // Replace registers that are only read by their values, wherever
+        // the analysis says they're constant.
+        fn propagate_constants(instructions: &Vec<Instruction>,
+                               arithmetic: Arithmetic) -> Vec<Instruction> {
+            let analysis = analyze(instructions, &[], arithmetic);
+            instructions.iter().enumerate().map(|(pc, &inst)| {
+                let fold = |o: Operand| match o {
+                    Register(r) => analysis.constant(pc, r).map_or(o, Value),
+                    Value(_) => o
+                };
+                match inst {
+                    Add(a, b)      => Add(a, fold(b)),
+                    Divide(a, b)   => Divide(a, fold(b)),
+                    Mod(a, b)      => Mod(a, fold(b)),
+                    Multiply(a, b) => Multiply(a, fold(b)),
+                    Set(a, b)      => Set(a, fold(b)),
+                    Sub(a, b)      => Sub(a, fold(b)),
+                    JumpGZ(a, b)   => JumpGZ(fold(a), fold(b)),
+                    JumpNZ(a, b)   => JumpNZ(fold(a), fold(b)),
+                    Call(a)        => Call(fold(a)),
+                    Out(a)         => Out(fold(a)),
+                    Push(a)        => Push(fold(a)),
+                    Send(a)        => Send(fold(a)),
+                    Pop(_) | Receive(_) | Return => inst
+                }
+            }).collect()
+        }
+
+        // Replace instructions the analysis says are unreachable with ones
+        // that do nothing.
+        fn blank_unreachable(instructions: &Vec<Instruction>,
+                             arithmetic: Arithmetic) -> Vec<Instruction> {
+            let analysis = analyze(instructions, &[], arithmetic);
+            instructions.iter().enumerate().map(|(pc, &inst)| {
+                if analysis.reachable(pc) {
+                    inst
+                } else {
+                    JumpNZ(Value(0), Value(0))
+                }
+            }).collect()
+        }
+
+        vec![("disassemble and reassemble", round_trip),
+             ("propagate constants", propagate_constants),
+             ("blank unreachable code", blank_unreachable)]
+    }
+
+    #[test]
+    fn generated_programs_terminate() {
+        for seed in 0..200 {
+            let instructions = random_program(&mut Rng::new(seed));
+            let outcome = execute(&instructions, Runner::Step,
+                                  Arithmetic::Wrapping);
+            assert!(outcome.steps < MAX_STEPS, "seed {}", seed);
+        }
+    }
+
+    const RUNNERS: [Runner; 4] = [
+        Runner::Run(Backend::Interpreter, u64::max_value()),
+        Runner::Run(Backend::Compiled, u64::max_value()),
+        Runner::Run(Backend::Compiled, 1),
+        Runner::Run(Backend::Compiled, 7)
+    ];
+
+    #[test]
+    fn backends_and_passes_agree_with_step() {
+        let policies = [Arithmetic::Checked, Arithmetic::Wrapping,
+                        Arithmetic::Saturating];
+        for seed in 0..500 {
+            let instructions = random_program(&mut Rng::new(seed));
+            let listing = disassemble(&instructions, &BTreeMap::new(), true);
+            let arithmetic = policies[seed as usize % policies.len()];
+            let reference = execute(&instructions, Runner::Step, arithmetic);
+            for &runner in &RUNNERS {
+                assert_eq!(execute(&instructions, runner, arithmetic),
+                           reference,
+                           "seed {}, {:?}, {:?}:\n{}",
+                           seed, runner, arithmetic, listing);
+            }
+            for (name, pass) in passes() {
+                let rewritten = pass(&instructions, arithmetic);
+                assert_eq!(execute(&rewritten, Runner::Step, arithmetic),
+                           reference,
+                           "seed {}, {}, {:?}:\n{}",
+                           seed, name, arithmetic, listing);
+            }
+        }
+    }
+    // Loops whose trip counts overflow isize when worked out, which the
+    // compiled backend has to hand back to step().
+    #[test]
+    fn extreme_counters_agree_with_step() {
+        let (t, x) = (Register('t'), Register('x'));
+        let programs = vec![
+            // The exit value starts at isize::MAX and counts down.
+            vec![Set(x, Value(isize::MAX - 1)),
+                 Sub(x, Value(1)),
+                 Set(t, x),
+                 Add(t, Value(2)),
+                 JumpGZ(t, Value(-3))],
+            // A jnz exit isize::MAX iterations away.
+            vec![Set(x, Value(isize::MIN)),
+                 Add(x, Value(1)),
+                 Set(t, x),
+                 JumpNZ(t, Value(-2))],
+            // A counter running down from isize::MIN.
+            vec![Set(x, Value(isize::MIN)),
+                 Sub(x, Value(1)),
+                 JumpGZ(x, Value(-1))],
+            vec![Set(x, Value(isize::MIN + 1)),
+                 Sub(x, Value(1)),
+                 JumpGZ(x, Value(-1))]
+        ];
+        for instructions in &programs {
+            for &arithmetic in &[Arithmetic::Checked, Arithmetic::Wrapping] {
+                let reference = execute(instructions, Runner::Step,
+                                        arithmetic);
+                for &runner in &RUNNERS {
+                    assert_eq!(execute(instructions, runner, arithmetic),
+                               reference,
+                               "{:?}, {:?}: {:?}",
+                               runner, arithmetic, instructions);
+                }
+            }
+        }
+    }
+}
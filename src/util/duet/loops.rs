@@ -0,0 +1,155 @@
+// Everything that determines what a program does next: if a program is ever
+// in the same MachineState twice, it will keep coming back to it forever
+// (provided nothing outside delivers new messages in between).
+#[derive(Clone,Debug,PartialEq)]
+pub struct MachineState {
+    pub pc: usize,
+    // Nonzero registers only, sorted, since unset registers read as zero.
+    pub registers: Vec<(char, isize)>,
+    pub stack: Vec<isize>,
+    pub call_stack: Vec<usize>,
+    pub io: Vec<isize>
+}
+
+// Finds cycles with Brent's algorithm: one saved state is compared against
+// every state that follows it, and is replaced by the current state after 1,
+// 2, 4, 8, ... steps. Once the saved state is inside a cycle and the distance
+// to the next replacement is at least the cycle's length, the program comes
+// back to the saved state after exactly one trip around the cycle. Memory use
+// is constant, and a loop is found within a few times (steps before the loop
+// + loop length) steps of starting.
+pub struct LoopDetector {
+    saved: Option<(u64, MachineState)>,
+    power: u64
+}
+
+impl LoopDetector {
+    pub fn new() -> Self {
+        LoopDetector {
+            saved: None,
+            power: 1
+        }
+    }
+
+    // Whether the state after `steps` steps, at `pc`, needs a call to check.
+    // States at a different pc from the saved one can't match it, so they
+    // only need checking when it's time to replace the saved state.
+    pub fn due(&self, steps: u64, pc: usize) -> bool {
+        match self.saved {
+            Some((saved_at, ref state)) => {
+                state.pc == pc || steps - saved_at >= self.power
+            },
+            None => true
+        }
+    }
+
+    // Forget everything seen so far, e.g. because a new message arrived and
+    // earlier states no longer predict the future.
+    pub fn reset(&mut self) {
+        self.saved = None;
+        self.power = 1;
+    }
+
+    // Look at the state after `steps` steps. If it's the saved state, returns
+    // the step count when that was saved: the difference is the length of the
+    // cycle.
+    pub fn check(&mut self, steps: u64, state: MachineState) -> Option<u64> {
+        if let Some((saved_at, ref saved)) = self.saved {
+            if *saved == state {
+                return Some(saved_at);
+            }
+            if steps - saved_at < self.power {
+                return None;
+            }
+            self.power *= 2;
+        }
+        self.saved = Some((steps, state));
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use util::duet::Program;
+    use util::duet::Instruction::*;
+    use util::duet::Operand::*;
+
+    #[test]
+    fn reports_exact_cycle_length() {
+        // a cycles through 1, 2, 0 and the loop is three instructions long.
+        let instructions = vec![Add(Register('a'), Value(1)),
+                                Mod(Register('a'), Value(3)),
+                                JumpNZ(Value(1), Value(-2))];
+        let mut program = Program::new(0, &instructions);
+        program.detect_loops();
+        let mut error = None;
+        for _ in 0..100 {
+            if let Err(e) = program.step() {
+                error = Some(e.to_string());
+                break;
+            }
+        }
+        let error = error.expect("loop not detected");
+        assert!(error.contains("repeats every 9 steps"), "{}", error);
+    }
+
+    #[test]
+    fn detection_executes_nothing_extra() {
+        // Sends 1 forever.
+        let instructions = vec![Send(Value(1)), JumpNZ(Value(1), Value(-1))];
+        let mut program = Program::new(0, &instructions);
+        program.detect_loops();
+        let mut calls = 0;
+        let mut sent = 0;
+        let error = loop {
+            match program.step() {
+                Ok(Some(_)) => sent += 1,
+                Ok(None) => {},
+                Err(e) => break e.to_string()
+            }
+            calls += 1;
+        };
+        assert!(error.contains("repeats every 2 steps"), "{}", error);
+        assert_eq!(program.steps(), calls);
+        assert_eq!(program.io().sent(), sent);
+    }
+
+    #[test]
+    fn terminating_programs_are_not_loops() {
+        let instructions = vec![Set(Register('a'), Value(50)),
+                                Sub(Register('a'), Value(1)),
+                                JumpNZ(Register('a'), Value(-1))];
+        let mut program = Program::new(0, &instructions);
+        program.detect_loops();
+        while program.is_running() {
+            program.step().unwrap();
+        }
+        assert_eq!(program.steps(), 101);
+    }
+
+    #[test]
+    fn step_budget_is_enforced() {
+        let instructions = vec![JumpNZ(Value(1), Value(0))];
+        let mut program = Program::new(0, &instructions);
+        program.set_step_limit(Some(10));
+        for _ in 0..10 {
+            program.step().unwrap();
+        }
+        let error = program.step().unwrap_err().to_string();
+        assert_eq!(error, "step budget of 10 exceeded at pc 0");
+
+        // A loop the detector hasn't caught yet is not proof there isn't one.
+        let instructions = vec![Add(Register('a'), Value(1)),
+                                JumpNZ(Value(1), Value(-1))];
+        let mut program = Program::new(0, &instructions);
+        program.detect_loops();
+        program.set_step_limit(Some(10));
+        let error = loop {
+            if let Err(e) = program.step() {
+                break e.to_string();
+            }
+        };
+        assert_eq!(error, "step budget of 10 exceeded at pc 0 \
+                           (no infinite loop detected yet)");
+    }
+}
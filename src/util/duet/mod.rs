@@ -0,0 +1,895 @@
+use failure::Error;
+use std::collections::{BTreeMap,HashMap,VecDeque};
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+
+mod arith;
+pub mod analyze;
+pub mod asm;
+mod compile;
+mod decode;
+pub mod disasm;
+#[cfg(test)]
+mod fuzz;
+mod loops;
+mod scheduler;
+mod snapshot;
+pub mod trace;
+
+pub use self::arith::{Arithmetic,ArithmeticError};
+pub use self::compile::Backend;
+pub use self::scheduler::{Policy,Scheduler};
+#[allow(unused_imports)]
+pub use self::scheduler::{Report,Stats};
+#[allow(unused_imports)]
+pub use self::snapshot::{IoState,Snapshot};
+use self::arith::{ArithmeticErrorKind,Operation};
+use self::compile::{Compiled,Stop};
+use self::decode::{Arg,Op};
+use self::loops::{LoopDetector,MachineState};
+use self::trace::{TraceEvent,TraceSink};
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Operand {
+    Register(char),
+    Value(isize)
+}
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Instruction {
+    Add(Operand, Operand),
+    JumpGZ(Operand, Operand),
+    JumpNZ(Operand, Operand),
+    Mod(Operand, Operand),
+    Multiply(Operand, Operand),
+    Receive(Operand),
+    Set(Operand, Operand),
+    Sub(Operand, Operand),
+    Send(Operand),
+    // The rest are only available in the extended dialect.
+    Call(Operand),
+    Divide(Operand, Operand),
+    Out(Operand),
+    Pop(Operand),
+    Push(Operand),
+    Return,
+}
+
+// Which instructions a program may use. Puzzle inputs are Strict; Extended
+// adds div, push/pop, call/ret and out for writing our own programs.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Dialect {
+    Strict,
+    Extended,
+}
+
+impl Instruction {
+    // This instruction printed with the register names in `names`, as the
+    // disassembler prints it.
+    pub fn named<'a>(&self, names: &'a HashMap<char, String>) -> Named<'a> {
+        Named {
+            instruction: *self,
+            names: names
+        }
+    }
+
+    pub fn mnemonic(&self) -> &'static str {
+        match *self {
+            Instruction::Add(_, _)      => "add",
+            Instruction::JumpGZ(_, _)   => "jgz",
+            Instruction::JumpNZ(_, _)   => "jnz",
+            Instruction::Mod(_, _)      => "mod",
+            Instruction::Multiply(_, _) => "mul",
+            Instruction::Receive(_)     => "rcv",
+            Instruction::Set(_, _)      => "set",
+            Instruction::Sub(_, _)      => "sub",
+            Instruction::Send(_)        => "snd",
+            Instruction::Call(_)        => "call",
+            Instruction::Divide(_, _)   => "div",
+            Instruction::Out(_)         => "out",
+            Instruction::Pop(_)         => "pop",
+            Instruction::Push(_)        => "push",
+            Instruction::Return         => "ret",
+        }
+    }
+
+    pub fn operands(&self) -> Vec<Operand> {
+        match *self {
+            Instruction::Add(a, b) |
+            Instruction::JumpGZ(a, b) |
+            Instruction::JumpNZ(a, b) |
+            Instruction::Mod(a, b) |
+            Instruction::Multiply(a, b) |
+            Instruction::Set(a, b) |
+            Instruction::Sub(a, b) |
+            Instruction::Divide(a, b) => vec![a, b],
+            Instruction::Receive(a) |
+            Instruction::Send(a) |
+            Instruction::Call(a) |
+            Instruction::Out(a) |
+            Instruction::Pop(a) |
+            Instruction::Push(a) => vec![a],
+            Instruction::Return => vec![],
+        }
+    }
+}
+
+// Registers print as their name, except those the assembler allocated for
+// multi-character names, which print as reg0, reg1, ... in allocation order.
+// Instruction::named and disasm::disassemble print the original names.
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Operand::Register(r) => match asm::named_register_index(r) {
+                Some(i) => write!(f, "reg{}", i),
+                None => write!(f, "{}", r)
+            },
+            Operand::Value(v) => write!(f, "{}", v)
+        }
+    }
+}
+
+// An instruction with names for some of its registers, which print in place
+// of the registers' own Display.
+pub struct Named<'a> {
+    instruction: Instruction,
+    names: &'a HashMap<char, String>
+}
+
+impl<'a> fmt::Display for Named<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.instruction.mnemonic())?;
+        for op in self.instruction.operands() {
+            match op {
+                Operand::Register(r) if self.names.contains_key(&r) => {
+                    write!(f, " {}", self.names[&r])?
+                },
+                _ => write!(f, " {}", op)?
+            }
+        }
+        Ok(())
+    }
+}
+
+// Instructions print as a line of duet source, e.g. "jgz a -2".
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.mnemonic())?;
+        for op in self.operands() {
+            write!(f, " {}", op)?;
+        }
+        Ok(())
+    }
+}
+
+// What a program should do after executing "rcv".
+pub enum Receive {
+    // Write the value into the instruction's register and continue.
+    Store(isize),
+    // Continue without touching the register.
+    Ignore,
+    // Nothing to receive yet; stay on this instruction and stop running.
+    Block,
+}
+
+// The semantics of the "snd" and "rcv" instructions. Part 1 of day 18 treats
+// them as playing and recovering sounds, part 2 as sending and receiving
+// messages between programs.
+pub trait Io {
+    // Called when the program executes "snd" with the given value.
+    fn send(&mut self, value: isize);
+
+    // Called when the program executes "rcv" on a register whose current
+    // value is `current`.
+    fn receive(&mut self, current: isize) -> Receive;
+
+    // Whatever this Io holds that can affect the program's future behavior,
+    // for comparing machine states. Statistics don't count.
+    fn state(&self) -> Vec<isize>;
+}
+
+// Part 2 semantics: "rcv" takes the next value from a message queue, blocking
+// while the queue is empty. Values sent are handed back to the caller of
+// Program::step, which is responsible for delivering them.
+#[derive(Clone,Debug,Default,PartialEq)]
+pub struct MessageQueue {
+    queue: VecDeque<isize>,
+    sent: usize,
+    received: usize
+}
+
+impl MessageQueue {
+    pub fn push(&mut self, value: isize) {
+        self.queue.push_back(value);
+    }
+
+    // Values delivered to this program but not yet received.
+    pub fn pending(&self) -> Vec<isize> {
+        self.queue.iter().cloned().collect()
+    }
+
+    // Number of "snd" instructions executed.
+    pub fn sent(&self) -> usize {
+        self.sent
+    }
+
+    // Number of "rcv" instructions that successfully took a value.
+    pub fn received(&self) -> usize {
+        self.received
+    }
+}
+
+impl Io for MessageQueue {
+    fn send(&mut self, _value: isize) {
+        self.sent += 1;
+    }
+
+    fn receive(&mut self, _current: isize) -> Receive {
+        match self.queue.pop_front() {
+            Some(v) => {
+                self.received += 1;
+                Receive::Store(v)
+            },
+            None => Receive::Block
+        }
+    }
+
+    fn state(&self) -> Vec<isize> {
+        self.pending()
+    }
+}
+
+// Part 1 semantics: "snd" plays a sound, and "rcv" recovers the frequency of
+// the last sound played, but only if its register is nonzero.
+#[derive(Clone,Debug,Default,PartialEq)]
+pub struct Sound {
+    last_played: Option<isize>,
+    recovered: Option<isize>
+}
+
+impl Sound {
+    // The most recently recovered frequency, if any "rcv" has fired.
+    pub fn recovered(&self) -> Option<isize> {
+        self.recovered
+    }
+}
+
+impl Io for Sound {
+    fn send(&mut self, value: isize) {
+        self.last_played = Some(value);
+    }
+
+    fn receive(&mut self, current: isize) -> Receive {
+        if current != 0 {
+            self.recovered = self.last_played;
+        }
+        Receive::Ignore
+    }
+
+    fn state(&self) -> Vec<isize> {
+        self.last_played.into_iter().chain(self.recovered).collect()
+    }
+}
+
+pub struct Program<'a, T: Io = MessageQueue> {
+    // Register values by slot; see decode::decode.
+    registers: Vec<isize>,
+    names: Vec<char>,
+    slots: HashMap<char, usize>,
+    instructions: &'a Vec<Instruction>,
+    ops: Vec<Op>,
+    io: T,
+    pc: usize,
+    running: bool,
+    dialect: Dialect,
+    arithmetic: Arithmetic,
+    // Present when the Compiled backend is selected.
+    compiled: Option<Compiled>,
+    // Values pushed by "push", and return addresses pushed by "call".
+    stack: Vec<isize>,
+    call_stack: Vec<usize>,
+    trace: Option<Box<dyn TraceSink + 'a>>,
+    steps: u64,
+    step_limit: Option<u64>,
+    loop_detector: Option<LoopDetector>,
+    // Source names for registers, used when printing instructions.
+    register_names: HashMap<char, String>
+}
+
+impl<'a> Program<'a> {
+    pub fn new(id: usize, instructions: &'a Vec<Instruction>) -> Self {
+        Program::with_io(id, instructions, MessageQueue::default())
+    }
+
+    pub fn add_to_queue(&mut self, value: isize) {
+        self.io.push(value);
+        if let Some(ref mut detector) = self.loop_detector {
+            detector.reset();
+        }
+    }
+}
+
+impl<'a, T: Io> Program<'a, T> {
+    // Create a program whose "snd" and "rcv" instructions are handled by `io`.
+    pub fn with_io(id: usize, instructions: &'a Vec<Instruction>, io: T)
+            -> Self {
+        let decoded = decode::decode(instructions);
+        let mut registers = vec![0; decoded.names.len()];
+        registers[decoded.slots[&'p']] = id as isize;
+        Program {
+            registers: registers,
+            names: decoded.names,
+            slots: decoded.slots,
+            instructions: instructions,
+            ops: decoded.ops,
+            io: io,
+            pc: 0,
+            running: true,
+            dialect: Dialect::Strict,
+            arithmetic: Arithmetic::Checked,
+            compiled: None,
+            stack: Vec::new(),
+            call_stack: Vec::new(),
+            trace: None,
+            steps: 0,
+            step_limit: None,
+            loop_detector: None,
+            register_names: HashMap::new()
+        }
+    }
+
+    pub fn io(&self) -> &T {
+        &self.io
+    }
+
+    // Programs start out Strict, and fail on extended instructions.
+    pub fn set_dialect(&mut self, dialect: Dialect) {
+        self.dialect = dialect;
+    }
+
+    // Programs start out Checked, and fail on overflow.
+    pub fn set_arithmetic(&mut self, arithmetic: Arithmetic) {
+        self.arithmetic = arithmetic;
+    }
+
+    // Choose how run() executes the program. Programs start out using the
+    // Interpreter.
+    pub fn set_backend(&mut self, backend: Backend) {
+        self.compiled = match backend {
+            Backend::Interpreter => None,
+            Backend::Compiled => Some(compile::compile(&self.ops))
+        };
+    }
+
+    // Print instructions in traces and errors with an assembly's names for
+    // its multi-character registers; see asm::Assembly.
+    pub fn set_register_names(&mut self, registers: &BTreeMap<String, char>) {
+        self.register_names = registers.iter()
+                                       .map(|(name, &r)| (r, name.clone()))
+                                       .collect();
+    }
+
+    // Record every instruction this program executes from now on.
+    pub fn set_trace<S: TraceSink + 'a>(&mut self, mut sink: S)
+            -> Result<(), Error> {
+        sink.start(&self.register_values())?;
+        self.trace = Some(Box::new(sink));
+        Ok(())
+    }
+
+    // Number of instructions executed so far.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    // Make step() fail once this many instructions have executed.
+    pub fn set_step_limit(&mut self, limit: Option<u64>) {
+        self.step_limit = limit;
+    }
+
+    // Make step() fail if the program is provably stuck in an infinite loop.
+    // This compares machine states as the program runs, which slows it down
+    // but takes constant memory.
+    pub fn detect_loops(&mut self) {
+        self.loop_detector = Some(LoopDetector::new());
+    }
+
+    fn machine_state(&self) -> MachineState {
+        let registers = self.register_values()
+                            .into_iter()
+                            .filter(|&(_, v)| v != 0)
+                            .collect();
+        MachineState {
+            pc: self.pc,
+            registers: registers,
+            stack: self.stack.clone(),
+            call_stack: self.call_stack.clone(),
+            io: self.io.state()
+        }
+    }
+
+    pub fn next_instruction(&self) -> Option<Instruction> {
+        if self.pc >= self.instructions.len() {
+            None
+        } else {
+            Some(self.instructions[self.pc])
+        }
+    }
+
+    // Execute one instruction. In the case the program sends a value ("snd"),
+    // then the result is Some(value), otherwise the result is None.
+    pub fn step(&mut self) -> Result<Option<isize>, Error> {
+        if self.is_terminated() {
+            return Ok(None);
+        }
+        if let Some(limit) = self.step_limit {
+            if self.steps >= limit {
+                // With loop detection on, running out of steps still leaves
+                // the question of whether the program would have finished.
+                let detail = if self.loop_detector.is_some() {
+                    " (no infinite loop detected yet)"
+                } else {
+                    ""
+                };
+                return Err(format_err!("step budget of {} exceeded at pc {}{}",
+                                       limit, self.pc, detail));
+            }
+        }
+        self.check_for_loop()?;
+        let result = if self.trace.is_some() {
+            self.execute_traced()?
+        } else {
+            self.execute()?
+        };
+        if !self.is_blocked() {
+            self.steps += 1;
+        }
+        Ok(result)
+    }
+
+    // Execute up to `max_steps` instructions, stopping early if the program
+    // terminates or blocks, or just after it sends a value. Returns how many
+    // instructions were executed, and the value sent if any, which as with
+    // step() is for the caller to deliver. Much faster than calling step()
+    // in a loop, unless tracing, loop detection or a step budget is on, in
+    // which case it does exactly that.
+    pub fn run(&mut self, max_steps: u64)
+            -> Result<(u64, Option<isize>), Error> {
+        let mut executed: u64 = 0;
+        if self.trace.is_some() || self.loop_detector.is_some() ||
+           self.step_limit.is_some() {
+            while executed < max_steps && !self.is_terminated() {
+                let sent = self.step()?;
+                if self.is_blocked() {
+                    break;
+                }
+                executed += 1;
+                if sent.is_some() {
+                    return Ok((executed, sent));
+                }
+            }
+            return Ok((executed, None));
+        }
+        let mut sent = None;
+        let mut result = Ok(());
+        while executed < max_steps && !self.is_terminated() {
+            if let Some(ref compiled) = self.compiled {
+                let (count, stop) = compiled.run(&mut self.registers,
+                                                 &mut self.pc,
+                                                 self.arithmetic,
+                                                 max_steps - executed);
+                executed += count;
+                match stop {
+                    Stop::Done => continue,
+                    Stop::Interpret => {},
+                    Stop::Error(kind) => {
+                        result = Err(self.arithmetic_error(kind));
+                        break;
+                    }
+                }
+            }
+            match self.execute() {
+                Ok(value) => sent = value,
+                Err(e) => result = Err(e)
+            }
+            if result.is_err() || self.is_blocked() {
+                break;
+            }
+            executed += 1;
+            if sent.is_some() {
+                break;
+            }
+        }
+        if self.is_terminated() {
+            self.running = false;
+        }
+        self.steps += executed;
+        result.map(|_| (executed, sent))
+    }
+
+    fn check_for_loop(&mut self) -> Result<(), Error> {
+        let due = match self.loop_detector {
+            Some(ref detector) => detector.due(self.steps, self.pc),
+            None => false
+        };
+        if !due || !self.running {
+            return Ok(());
+        }
+        let state = self.machine_state();
+        let first_seen = self.loop_detector
+                             .as_mut()
+                             .unwrap()
+                             .check(self.steps, state);
+        if let Some(first_seen) = first_seen {
+            return Err(format_err!(
+                "infinite loop detected at pc {} (first seen at step {}): \
+                 state repeats every {} steps",
+                self.pc, first_seen, self.steps - first_seen));
+        }
+        Ok(())
+    }
+
+    // Execute one instruction and send it to the trace sink.
+    fn execute_traced(&mut self) -> Result<Option<isize>, Error> {
+        let pc = self.pc;
+        let inst = match self.next_instruction() {
+            Some(inst) => inst,
+            None => return Ok(None)
+        };
+        let destination = trace::destination(inst);
+        let before = destination.map(|r| self.read_register(r));
+        let result = self.execute()?;
+        if self.is_blocked() {
+            // A "rcv" with nothing to receive; nothing actually happened.
+            return Ok(result);
+        }
+        let mut changes = Vec::new();
+        if let (Some(r), Some(old)) = (destination, before) {
+            let new = self.read_register(r);
+            if new != old {
+                changes.push((r, old, new));
+            }
+        }
+        let event = TraceEvent {
+            pc: pc,
+            instruction: inst.named(&self.register_names).to_string(),
+            changes: changes
+        };
+        self.trace.as_mut().unwrap().record(&event)?;
+        Ok(result)
+    }
+
+    fn execute(&mut self) -> Result<Option<isize>, Error> {
+        let mut result: Option<isize> = None;
+        let mut advance_pc: bool = true;
+        let op = match self.ops.get(self.pc) {
+            Some(&op) => op,
+            None => return Ok(None)
+        };
+        match op {
+            Op::Add(r, arg) => {
+                let v1 = self.registers[r];
+                let v2 = self.value(arg);
+                self.registers[r] = self.arithmetic(Operation::Add, v1, v2)?;
+            },
+            Op::JumpGZ(arg1, arg2) => {
+                if self.value(arg1) > 0 {
+                    let offset = self.value(arg2);
+                    self.jump(offset)?;
+                    advance_pc = false;
+                }
+            },
+            Op::JumpNZ(arg1, arg2) => {
+                if self.value(arg1) != 0 {
+                    let offset = self.value(arg2);
+                    self.jump(offset)?;
+                    advance_pc = false;
+                }
+            },
+            Op::Mod(r, arg) => {
+                let v1 = self.registers[r];
+                let v2 = self.value(arg);
+                self.registers[r] = self.arithmetic(Operation::Mod, v1, v2)?;
+            },
+            Op::Multiply(r, arg) => {
+                let v1 = self.registers[r];
+                let v2 = self.value(arg);
+                self.registers[r] =
+                    self.arithmetic(Operation::Multiply, v1, v2)?;
+            },
+            Op::Receive(r) => {
+                let current = self.registers[r];
+                match self.io.receive(current) {
+                    Receive::Store(v) => {
+                        self.registers[r] = v;
+                        self.running = true;
+                    },
+                    Receive::Ignore => {
+                        self.running = true;
+                    },
+                    Receive::Block => {
+                        self.running = false;
+                        advance_pc = false;
+                    }
+                }
+            },
+            Op::Set(r, arg) => {
+                self.registers[r] = self.value(arg);
+            },
+            Op::Send(arg) => {
+                let v = self.value(arg);
+                self.io.send(v);
+                result = Some(v);
+            },
+            Op::Sub(r, arg) => {
+                let v1 = self.registers[r];
+                let v2 = self.value(arg);
+                self.registers[r] = self.arithmetic(Operation::Sub, v1, v2)?;
+            },
+            Op::Call(arg) => {
+                self.require_extended()?;
+                let offset = self.value(arg);
+                let return_pc = self.pc + 1;
+                self.jump(offset)?;
+                self.call_stack.push(return_pc);
+                advance_pc = false;
+            },
+            Op::Divide(r, arg) => {
+                self.require_extended()?;
+                let v1 = self.registers[r];
+                let v2 = self.value(arg);
+                self.registers[r] =
+                    self.arithmetic(Operation::Divide, v1, v2)?;
+            },
+            Op::Out(arg) => {
+                self.require_extended()?;
+                println!("{}", self.value(arg));
+            },
+            Op::Pop(r) => {
+                self.require_extended()?;
+                match self.stack.pop() {
+                    Some(v) => self.registers[r] = v,
+                    None => return Err(format_err!(
+                                "pop from empty stack at pc {}", self.pc))
+                }
+            },
+            Op::Push(arg) => {
+                self.require_extended()?;
+                let v = self.value(arg);
+                self.stack.push(v);
+            },
+            Op::Return => {
+                self.require_extended()?;
+                match self.call_stack.pop() {
+                    Some(pc) => self.pc = pc,
+                    None => return Err(format_err!(
+                                "ret with empty call stack at pc {}", self.pc))
+                }
+                advance_pc = false;
+            },
+            Op::Invalid => {
+                // One of the instructions that writes to its first operand
+                // was given a value instead of a register. The instructions
+                // are bad and we should give up.
+                return Err(format_err!(
+                    "Bad instruction: {:?}", self.instructions[self.pc]));
+            }
+        }
+        if advance_pc {
+            self.pc += 1;
+        }
+        if self.pc >= self.ops.len() {
+            self.running = false;
+        }
+        Ok(result)
+    }
+
+    fn require_extended(&self) -> Result<(), Error> {
+        if self.dialect != Dialect::Extended {
+            return Err(format_err!(
+                "{} is only available in the extended dialect (pc {})",
+                self.instructions[self.pc].mnemonic(), self.pc));
+        }
+        Ok(())
+    }
+
+    // Apply the program's Arithmetic setting to the current instruction.
+    fn arithmetic(&self, op: Operation, a: isize, b: isize)
+            -> Result<isize, Error> {
+        self.arithmetic.apply(op, a, b).map_err(|kind| {
+            self.arithmetic_error(kind)
+        })
+    }
+
+    fn arithmetic_error(&self, kind: ArithmeticErrorKind) -> Error {
+        let instruction = self.instructions[self.pc];
+        ArithmeticError {
+            kind: kind,
+            pc: self.pc,
+            instruction: instruction.named(&self.register_names).to_string()
+        }.into()
+    }
+
+    fn value(&self, arg: Arg) -> isize {
+        match arg {
+            Arg::Slot(r) => self.registers[r],
+            Arg::Value(v) => v
+        }
+    }
+
+    // False if program terminated or is waiting for a message.
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    // True once the pc has left the program (normally by running off the end).
+    pub fn is_terminated(&self) -> bool {
+        self.pc >= self.ops.len()
+    }
+
+    // True if the program is waiting on "rcv" for a value that isn't there.
+    pub fn is_blocked(&self) -> bool {
+        !self.running && !self.is_terminated()
+    }
+
+    pub fn read_register(&self, r: char) -> isize {
+        match self.slots.get(&r) {
+            Some(&slot) => self.registers[slot],
+            None => 0
+        }
+    }
+
+    // Registers in order, leaving out the ones that are zero (apart from p,
+    // which every program has).
+    pub fn register_values(&self) -> Vec<(char, isize)> {
+        let mut values = self.names
+                             .iter()
+                             .cloned()
+                             .zip(self.registers.iter().cloned())
+                             .filter(|&(r, v)| v != 0 || r == 'p')
+                             .collect::<Vec<(char, isize)>>();
+        values.sort();
+        values
+    }
+
+    pub fn store(&mut self, r: char, v: isize) {
+        //println!("Setting {} to {}", r, v);
+        let slot = match self.slots.get(&r) {
+            Some(&slot) => slot,
+            None => {
+                // Not mentioned by any instruction, but it can still be read.
+                self.names.push(r);
+                self.registers.push(0);
+                self.slots.insert(r, self.names.len() - 1);
+                self.names.len() - 1
+            }
+        };
+        self.registers[slot] = v;
+    }
+
+    pub fn jump(&mut self, offset: isize) -> Result<(), Error> {
+        let tmp_pc = self.pc as isize + offset;
+        if tmp_pc < 0 || tmp_pc > self.instructions.len() as isize {
+            // Treats jumping just past end as ok, everything else is an error.
+            return Err(format_err!("Jumped out of range: {}", tmp_pc));
+        }
+        self.pc = tmp_pc as usize;
+        Ok(())
+    }
+}
+
+// Read and assemble a duet source file. See asm::assemble for the syntax.
+pub fn parse_instructions(file: &str) -> Result<Vec<Instruction>, Error> {
+    let mut source = String::new();
+    File::open(file)?.read_to_string(&mut source)?;
+    let assembly = asm::assemble(&source)
+                       .map_err(|e| format_err!("{}: {}", file, e))?;
+    Ok(assembly.instructions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Backend,Dialect,Program};
+    use util::duet::asm::{assemble,assemble_dialect};
+    use util::duet::Instruction::*;
+    use util::duet::Operand::*;
+
+    #[test]
+    fn extended_dialect_recursion() {
+        let source = "
+            .const N 5
+                    set n N
+                    call fact
+                    div r 7
+                    jgz 1 end
+            # r = n!, using the stack to keep n across the recursive call.
+            fact:   jgz n recurse
+                    set r 1
+                    ret
+            recurse:
+                    push n
+                    sub n 1
+                    call fact
+                    pop n
+                    mul r n
+                    ret
+            end:
+        ";
+        let assembly = assemble_dialect(source, Dialect::Extended).unwrap();
+        let mut program = Program::new(0, &assembly.instructions);
+        program.set_dialect(Dialect::Extended);
+        while program.is_running() {
+            program.step().unwrap();
+        }
+        assert_eq!(program.read_register('r'), 120 / 7);
+        assert_eq!(program.read_register('n'), 5);
+        assert!(program.stack.is_empty() && program.call_stack.is_empty());
+    }
+
+    #[test]
+    fn run_stops_when_blocked() {
+        let instructions = vec![Set(Register('a'), Value(2)),
+                                Receive(Register('b')),
+                                Add(Register('a'), Register('b'))];
+        let mut program = Program::new(0, &instructions);
+        assert_eq!(program.run(100).unwrap(), (1, None));
+        assert!(program.is_blocked());
+        program.add_to_queue(5);
+        assert_eq!(program.run(100).unwrap(), (2, None));
+        assert!(program.is_terminated());
+        assert_eq!(program.read_register('a'), 7);
+        assert_eq!(program.steps(), 3);
+    }
+
+    #[test]
+    fn run_hands_back_values_sent_in_loops() {
+        // Each value sent comes straight back to the same program.
+        let instructions = assemble("
+                    set c 3
+            loop:   snd c
+                    snd 10
+                    rcv a
+                    add b a
+                    rcv a
+                    add b a
+                    sub c 1
+                    jgz c loop
+        ").unwrap().instructions;
+        let mut reference = Program::new(0, &instructions);
+        while !reference.is_terminated() && !reference.is_blocked() {
+            if let Some(v) = reference.step().unwrap() {
+                reference.add_to_queue(v);
+            }
+        }
+        assert_eq!(reference.read_register('b'), 3 + 2 + 1 + 30);
+        for &backend in &[Backend::Interpreter, Backend::Compiled] {
+            let mut program = Program::new(0, &instructions);
+            program.set_backend(backend);
+            while !program.is_terminated() && !program.is_blocked() {
+                if let (_, Some(v)) = program.run(100).unwrap() {
+                    program.add_to_queue(v);
+                }
+            }
+            assert_eq!(program.register_values(), reference.register_values());
+            assert_eq!(program.steps(), reference.steps());
+            assert_eq!(program.io().sent(), reference.io().sent());
+        }
+    }
+
+    #[test]
+    fn extended_instructions_need_the_dialect() {
+        let instructions = vec![Set(Register('a'), Value(6)),
+                                Divide(Register('a'), Value(2))];
+        let mut program = Program::new(0, &instructions);
+        program.step().unwrap();
+        assert_eq!(program.step().unwrap_err().to_string(),
+                   "div is only available in the extended dialect (pc 1)");
+
+        let instructions = vec![Return];
+        let mut program = Program::new(0, &instructions);
+        program.set_dialect(Dialect::Extended);
+        assert_eq!(program.step().unwrap_err().to_string(),
+                   "ret with empty call stack at pc 0");
+    }
+}
@@ -0,0 +1,213 @@
+use failure::Error;
+use super::{Instruction,Program};
+
+// How the scheduler shares time between its programs.
+#[derive(Clone,Copy,Debug)]
+pub enum Policy {
+    // Execute one instruction from each program in turn.
+    RoundRobin,
+    // Let each program run until it blocks or terminates before moving on.
+    // A program that never blocks will starve the others.
+    #[allow(dead_code)]
+    RunUntilBlocked,
+}
+
+// Message counts for a single program.
+#[derive(Clone,Copy,Debug,Default,PartialEq)]
+pub struct Stats {
+    pub sent: usize,
+    pub received: usize
+}
+
+// The state of the world once no program can make progress.
+#[derive(Clone,Debug)]
+pub struct Report {
+    // Programs that ran off the end of their instructions.
+    #[allow(dead_code)]
+    pub terminated: Vec<usize>,
+    // Programs stuck waiting on "rcv".
+    #[allow(dead_code)]
+    pub deadlocked: Vec<usize>,
+    // Undelivered values left in each program's queue.
+    #[allow(dead_code)]
+    pub queues: Vec<Vec<isize>>,
+    pub stats: Vec<Stats>
+}
+
+// Runs any number of message-passing programs, delivering each value a
+// program sends to every program it is connected to. Values sent by a program
+// with no outgoing connections are dropped.
+pub struct Scheduler<'a> {
+    programs: Vec<Program<'a>>,
+    routes: Vec<Vec<usize>>,
+    policy: Policy
+}
+
+impl<'a> Scheduler<'a> {
+    pub fn new(policy: Policy) -> Self {
+        Scheduler {
+            programs: Vec::new(),
+            routes: Vec::new(),
+            policy: policy
+        }
+    }
+
+    // Two copies of a program sending to each other, as in day 18.
+    pub fn pair(instructions: &'a Vec<Instruction>, policy: Policy) -> Self {
+        let mut scheduler = Self::spawn(2, instructions, policy);
+        scheduler.connect(0, 1);
+        scheduler.connect(1, 0);
+        scheduler
+    }
+
+    // n copies of a program, each sending to the next and the last sending
+    // back around to the first.
+    #[allow(dead_code)]
+    pub fn ring(n: usize, instructions: &'a Vec<Instruction>, policy: Policy)
+            -> Self {
+        let mut scheduler = Self::spawn(n, instructions, policy);
+        for i in 0..n {
+            scheduler.connect(i, (i+1) % n);
+        }
+        scheduler
+    }
+
+    // n copies of a program, each sending to the next. The last program's
+    // output is dropped.
+    #[allow(dead_code)]
+    pub fn pipeline(n: usize, instructions: &'a Vec<Instruction>,
+                    policy: Policy) -> Self {
+        let mut scheduler = Self::spawn(n, instructions, policy);
+        for i in 1..n {
+            scheduler.connect(i-1, i);
+        }
+        scheduler
+    }
+
+    // Add n unconnected copies of a program, with ids 0..n.
+    fn spawn(n: usize, instructions: &'a Vec<Instruction>, policy: Policy)
+            -> Self {
+        let mut scheduler = Scheduler::new(policy);
+        for id in 0..n {
+            scheduler.add_program(Program::new(id, instructions));
+        }
+        scheduler
+    }
+
+    // Add a program to the scheduler. Returns its index for use with connect.
+    pub fn add_program(&mut self, program: Program<'a>) -> usize {
+        self.programs.push(program);
+        self.routes.push(Vec::new());
+        self.programs.len() - 1
+    }
+
+    // Deliver everything program `from` sends to program `to`.
+    pub fn connect(&mut self, from: usize, to: usize) {
+        self.routes[from].push(to);
+    }
+
+    #[allow(dead_code)]
+    pub fn program(&self, index: usize) -> &Program<'a> {
+        &self.programs[index]
+    }
+
+    pub fn stats(&self, index: usize) -> Stats {
+        let io = self.programs[index].io();
+        Stats {
+            sent: io.sent(),
+            received: io.received()
+        }
+    }
+
+    // Run until every program has either terminated or is blocked with an
+    // empty queue.
+    pub fn run(&mut self) -> Result<Report, Error> {
+        loop {
+            let mut progressed = false;
+            for i in 0..self.programs.len() {
+                match self.policy {
+                    Policy::RoundRobin => {
+                        if self.step_program(i)? {
+                            progressed = true;
+                        }
+                    },
+                    Policy::RunUntilBlocked => {
+                        while self.step_program(i)? {
+                            progressed = true;
+                        }
+                    }
+                }
+            }
+            if !progressed {
+                break;
+            }
+        }
+        Ok(self.report())
+    }
+
+    // Execute one instruction of program i and deliver anything it sent.
+    // Returns false if the program couldn't make progress.
+    fn step_program(&mut self, i: usize) -> Result<bool, Error> {
+        if self.programs[i].is_terminated() {
+            return Ok(false);
+        }
+        if let Some(v) = self.programs[i].step()? {
+            for &to in &self.routes[i] {
+                self.programs[to].add_to_queue(v);
+            }
+        }
+        Ok(!self.programs[i].is_blocked())
+    }
+
+    fn report(&self) -> Report {
+        let n = self.programs.len();
+        Report {
+            terminated: (0..n).filter(|&i| self.programs[i].is_terminated())
+                              .collect(),
+            deadlocked: (0..n).filter(|&i| self.programs[i].is_blocked())
+                              .collect(),
+            queues: self.programs.iter()
+                                 .map(|p| p.io().pending())
+                                 .collect(),
+            stats: (0..n).map(|i| self.stats(i)).collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Policy,Scheduler,Stats};
+    use util::duet::Instruction::*;
+    use util::duet::Operand::*;
+
+    #[test]
+    fn ring_passes_ids_along() {
+        let instructions = vec![Send(Register('p')), Receive(Register('a'))];
+        for &policy in &[Policy::RoundRobin, Policy::RunUntilBlocked] {
+            let mut scheduler = Scheduler::ring(3, &instructions, policy);
+            let report = scheduler.run().unwrap();
+            assert_eq!(report.terminated, vec![0, 1, 2]);
+            assert!(report.deadlocked.is_empty());
+            assert_eq!(scheduler.program(0).read_register('a'), 2);
+            assert_eq!(scheduler.program(1).read_register('a'), 0);
+            assert_eq!(scheduler.program(2).read_register('a'), 1);
+            assert_eq!(report.stats[0], Stats { sent: 1, received: 1 });
+        }
+    }
+
+    #[test]
+    fn pipeline_reports_deadlock() {
+        // Every program waits for two values but the first never gets any.
+        let instructions = vec![Send(Register('p')),
+                                Receive(Register('a')),
+                                Receive(Register('b'))];
+        let mut scheduler = Scheduler::pipeline(3, &instructions,
+                                                Policy::RoundRobin);
+        let report = scheduler.run().unwrap();
+        assert!(report.terminated.is_empty());
+        assert_eq!(report.deadlocked, vec![0, 1, 2]);
+        assert!(report.queues.iter().all(|q| q.is_empty()));
+        assert_eq!(report.stats[1], Stats { sent: 1, received: 1 });
+        assert_eq!(report.stats[0], Stats { sent: 1, received: 0 });
+    }
+}
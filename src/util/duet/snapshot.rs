@@ -0,0 +1,560 @@
+use failure::Error;
+use std::collections::BTreeMap;
+use std::iter::Peekable;
+use std::str::Chars;
+use super::{Io,MessageQueue,Program,Sound};
+
+// A copy of everything that changes while a program runs. Snapshots can be
+// restored into any program running the same instructions, and written to or
+// read from a line-based text format or JSON.
+//
+// The program's settings (dialect, arithmetic, backend, step limit and trace)
+// belong to the program and aren't saved. Neither are the states the loop
+// detector has seen: restoring a snapshot starts its history afresh.
+//
+// Nothing in the puzzles needs to checkpoint a program yet.
+#[allow(dead_code)]
+#[derive(Clone,Debug,PartialEq)]
+pub struct Snapshot<T> {
+    pub registers: BTreeMap<char, isize>,
+    pub pc: usize,
+    pub steps: u64,
+    pub running: bool,
+    // Only used by the extended dialect.
+    pub stack: Vec<isize>,
+    pub call_stack: Vec<usize>,
+    pub io: T
+}
+
+// Io implementations whose state can be serialized, as a set of named lists
+// of values.
+#[allow(dead_code)]
+pub trait IoState: Sized {
+    fn save(&self) -> Vec<(&'static str, Vec<isize>)>;
+    fn load(fields: &BTreeMap<String, Vec<isize>>) -> Result<Self, Error>;
+}
+
+impl IoState for MessageQueue {
+    fn save(&self) -> Vec<(&'static str, Vec<isize>)> {
+        vec![("queue", self.pending()),
+             ("sent", vec![self.sent as isize]),
+             ("received", vec![self.received as isize])]
+    }
+
+    fn load(fields: &BTreeMap<String, Vec<isize>>) -> Result<Self, Error> {
+        Ok(MessageQueue {
+            queue: io_field(fields, "queue")?.iter().cloned().collect(),
+            sent: io_scalar(fields, "sent")? as usize,
+            received: io_scalar(fields, "received")? as usize
+        })
+    }
+}
+
+impl IoState for Sound {
+    fn save(&self) -> Vec<(&'static str, Vec<isize>)> {
+        vec![("last_played", self.last_played.into_iter().collect()),
+             ("recovered", self.recovered.into_iter().collect())]
+    }
+
+    fn load(fields: &BTreeMap<String, Vec<isize>>) -> Result<Self, Error> {
+        Ok(Sound {
+            last_played: io_field(fields, "last_played")?.first().cloned(),
+            recovered: io_field(fields, "recovered")?.first().cloned()
+        })
+    }
+}
+
+fn io_field<'a>(fields: &'a BTreeMap<String, Vec<isize>>, name: &str)
+        -> Result<&'a Vec<isize>, Error> {
+    match fields.get(name) {
+        Some(values) => Ok(values),
+        None => Err(format_err!("snapshot is missing io field: {}", name))
+    }
+}
+
+fn io_scalar(fields: &BTreeMap<String, Vec<isize>>, name: &str)
+        -> Result<isize, Error> {
+    let values = io_field(fields, name)?;
+    if values.len() != 1 {
+        return Err(format_err!("io field {} should have one value", name));
+    }
+    Ok(values[0])
+}
+
+#[allow(dead_code)]
+impl<'a, T: Io + Clone> Program<'a, T> {
+    pub fn snapshot(&self) -> Snapshot<T> {
+        Snapshot {
+            registers: self.register_values().into_iter().collect(),
+            pc: self.pc,
+            steps: self.steps,
+            running: self.running,
+            stack: self.stack.clone(),
+            call_stack: self.call_stack.clone(),
+            io: self.io.clone()
+        }
+    }
+
+    // Replace this program's state with a snapshot's. Fails if the snapshot's
+    // pc doesn't fit these instructions.
+    pub fn restore(&mut self, snapshot: &Snapshot<T>) -> Result<(), Error> {
+        if snapshot.pc > self.instructions.len() {
+            return Err(format_err!("snapshot pc {} out of range", snapshot.pc));
+        }
+        for v in self.registers.iter_mut() {
+            *v = 0;
+        }
+        for (&r, &v) in &snapshot.registers {
+            self.store(r, v);
+        }
+        self.pc = snapshot.pc;
+        self.steps = snapshot.steps;
+        self.running = snapshot.running;
+        self.stack = snapshot.stack.clone();
+        self.call_stack = snapshot.call_stack.clone();
+        self.io = snapshot.io.clone();
+        if let Some(ref mut detector) = self.loop_detector {
+            detector.reset();
+        }
+        Ok(())
+    }
+}
+
+#[allow(dead_code)]
+impl<T: IoState> Snapshot<T> {
+    // One field per line, e.g.:
+    //
+    //   pc 21
+    //   steps 1400
+    //   running true
+    //   reg a 127
+    //   reg p 1
+    //   stack 4 4
+    //   calls 9
+    //   io queue 5 -3
+    //
+    // The stack lines are left out when the stacks are empty.
+    pub fn to_text(&self) -> String {
+        let mut result = String::new();
+        result.push_str(&format!("pc {}\n", self.pc));
+        result.push_str(&format!("steps {}\n", self.steps));
+        result.push_str(&format!("running {}\n", self.running));
+        for (r, v) in &self.registers {
+            result.push_str(&format!("reg {} {}\n", r, v));
+        }
+        if !self.stack.is_empty() {
+            result.push_str(&format!("stack{}\n", join_values(&self.stack)));
+        }
+        if !self.call_stack.is_empty() {
+            result.push_str(&format!("calls{}\n",
+                                     join_values(&self.call_stack)));
+        }
+        for (name, values) in self.io.save() {
+            result.push_str("io ");
+            result.push_str(name);
+            for v in values {
+                result.push_str(&format!(" {}", v));
+            }
+            result.push('\n');
+        }
+        result
+    }
+
+    pub fn from_text(text: &str) -> Result<Snapshot<T>, Error> {
+        let mut pc: Option<usize> = None;
+        let mut steps: Option<u64> = None;
+        let mut running: Option<bool> = None;
+        let mut registers = BTreeMap::new();
+        let mut stack = Vec::new();
+        let mut call_stack = Vec::new();
+        let mut io_fields = BTreeMap::new();
+        for line in text.lines().filter(|l| !l.trim().is_empty()) {
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            match (tokens[0], tokens.len()) {
+                ("pc", 2) => pc = Some(tokens[1].parse::<usize>()?),
+                ("steps", 2) => steps = Some(tokens[1].parse::<u64>()?),
+                ("running", 2) => running = Some(tokens[1].parse::<bool>()?),
+                ("reg", 3) => {
+                    registers.insert(parse_register(tokens[1])?,
+                                     tokens[2].parse::<isize>()?);
+                },
+                ("stack", _) => {
+                    for token in &tokens[1..] {
+                        stack.push(token.parse::<isize>()?);
+                    }
+                },
+                ("calls", _) => {
+                    for token in &tokens[1..] {
+                        call_stack.push(token.parse::<usize>()?);
+                    }
+                },
+                ("io", n) if n >= 2 => {
+                    let mut values = Vec::new();
+                    for token in &tokens[2..] {
+                        values.push(token.parse::<isize>()?);
+                    }
+                    io_fields.insert(tokens[1].to_string(), values);
+                },
+                _ => return Err(format_err!("bad snapshot line: {}", line))
+            }
+        }
+        Ok(Snapshot {
+            registers: registers,
+            pc: pc.ok_or_else(|| format_err!("snapshot is missing pc"))?,
+            steps: steps.ok_or_else(
+                       || format_err!("snapshot is missing steps"))?,
+            running: running.ok_or_else(
+                         || format_err!("snapshot is missing running"))?,
+            stack: stack,
+            call_stack: call_stack,
+            io: T::load(&io_fields)?
+        })
+    }
+
+    // The same fields as the text format, as a single JSON object:
+    //
+    //   {"pc":21,"steps":1400,"running":true,"registers":{"a":127,"p":1},
+    //    "stack":[4,4],"calls":[9],
+    //    "io":{"queue":[5,-3],"sent":[4],"received":[2]}}
+    //
+    // "stack" and "calls" may be left out when reading.
+    pub fn to_json(&self) -> String {
+        let registers = self.registers
+                            .iter()
+                            .map(|(r, v)| format!("{}:{}",
+                                                  json_string(&r.to_string()),
+                                                  v))
+                            .collect::<Vec<String>>();
+        let io = self.io
+                     .save()
+                     .iter()
+                     .map(|&(name, ref values)| {
+                         let values = values.iter()
+                                            .map(|v| v.to_string())
+                                            .collect::<Vec<String>>();
+                         format!("{}:[{}]", json_string(name),
+                                 values.join(","))
+                     })
+                     .collect::<Vec<String>>();
+        let calls = self.call_stack
+                        .iter()
+                        .map(|v| v.to_string())
+                        .collect::<Vec<String>>();
+        let stack = self.stack
+                        .iter()
+                        .map(|v| v.to_string())
+                        .collect::<Vec<String>>();
+        format!(concat!("{{\"pc\":{},\"steps\":{},\"running\":{},",
+                        "\"registers\":{{{}}},",
+                        "\"stack\":[{}],\"calls\":[{}],",
+                        "\"io\":{{{}}}}}"),
+                self.pc, self.steps, self.running, registers.join(","),
+                stack.join(","), calls.join(","), io.join(","))
+    }
+
+    pub fn from_json(text: &str) -> Result<Snapshot<T>, Error> {
+        let mut parser = JsonParser { chars: text.chars().peekable() };
+        let value = parser.parse_value()?;
+        parser.skip_whitespace();
+        if parser.chars.peek().is_some() {
+            return Err(format_err!("trailing characters after snapshot JSON"));
+        }
+
+        let mut pc: Option<usize> = None;
+        let mut steps: Option<u64> = None;
+        let mut running: Option<bool> = None;
+        let mut registers = BTreeMap::new();
+        let mut stack = Vec::new();
+        let mut call_stack = Vec::new();
+        let mut io_fields = BTreeMap::new();
+        for (key, value) in value.into_object()? {
+            match (key.as_str(), value) {
+                ("pc", Json::Number(n)) if n >= 0 => pc = Some(n as usize),
+                ("steps", Json::Number(n)) if n >= 0 => {
+                    steps = Some(n as u64)
+                },
+                ("running", Json::Bool(b)) => running = Some(b),
+                ("registers", value) => {
+                    for (r, v) in value.into_object()? {
+                        registers.insert(parse_register(&r)?, v.into_number()?);
+                    }
+                },
+                ("stack", value) => {
+                    for v in value.into_array()? {
+                        stack.push(v.into_number()?);
+                    }
+                },
+                ("calls", value) => {
+                    for v in value.into_array()? {
+                        match v.into_number()? {
+                            n if n >= 0 => call_stack.push(n as usize),
+                            n => return Err(format_err!(
+                                     "bad return address: {}", n))
+                        }
+                    }
+                },
+                ("io", value) => {
+                    for (name, values) in value.into_object()? {
+                        let mut numbers = Vec::new();
+                        for v in values.into_array()? {
+                            numbers.push(v.into_number()?);
+                        }
+                        io_fields.insert(name, numbers);
+                    }
+                },
+                (key, _) => return Err(format_err!(
+                                "unexpected snapshot field: {}", key))
+            }
+        }
+        Ok(Snapshot {
+            registers: registers,
+            pc: pc.ok_or_else(|| format_err!("snapshot is missing pc"))?,
+            steps: steps.ok_or_else(
+                       || format_err!("snapshot is missing steps"))?,
+            running: running.ok_or_else(
+                         || format_err!("snapshot is missing running"))?,
+            stack: stack,
+            call_stack: call_stack,
+            io: T::load(&io_fields)?
+        })
+    }
+}
+
+fn join_values<V: ToString>(values: &[V]) -> String {
+    values.iter().map(|v| format!(" {}", v.to_string())).collect()
+}
+
+fn parse_register(token: &str) -> Result<char, Error> {
+    let mut chars = token.chars();
+    match (chars.next(), chars.next()) {
+        (Some(r), None) => Ok(r),
+        _ => Err(format_err!("bad register name: {}", token))
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut result = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"'  => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            c    => result.push(c)
+        }
+    }
+    result.push('"');
+    result
+}
+
+// Just enough JSON to read back what to_json writes.
+enum Json {
+    Number(isize),
+    Bool(bool),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>)
+}
+
+impl Json {
+    fn into_number(self) -> Result<isize, Error> {
+        match self {
+            Json::Number(n) => Ok(n),
+            _ => Err(format_err!("expected a number in snapshot JSON"))
+        }
+    }
+
+    fn into_array(self) -> Result<Vec<Json>, Error> {
+        match self {
+            Json::Array(values) => Ok(values),
+            _ => Err(format_err!("expected an array in snapshot JSON"))
+        }
+    }
+
+    fn into_object(self) -> Result<Vec<(String, Json)>, Error> {
+        match self {
+            Json::Object(fields) => Ok(fields),
+            _ => Err(format_err!("expected an object in snapshot JSON"))
+        }
+    }
+}
+
+struct JsonParser<'a> {
+    chars: Peekable<Chars<'a>>
+}
+
+impl<'a> JsonParser<'a> {
+    fn skip_whitespace(&mut self) {
+        while self.chars.peek().map_or(false, |c| c.is_whitespace()) {
+            self.chars.next();
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), Error> {
+        self.skip_whitespace();
+        match self.chars.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(format_err!("expected '{}', found '{}'",
+                                       expected, c)),
+            None => Err(format_err!("expected '{}', found end of input",
+                                    expected))
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Json, Error> {
+        self.skip_whitespace();
+        match self.chars.peek().cloned() {
+            Some('{') => self.parse_object(),
+            Some('[') => self.parse_array(),
+            Some(c) if c == '-' || c.is_digit(10) => {
+                let mut digits = String::new();
+                while self.chars.peek().map_or(false, |&c| c == '-' ||
+                                                           c.is_digit(10)) {
+                    digits.push(self.chars.next().unwrap());
+                }
+                Ok(Json::Number(digits.parse::<isize>()?))
+            },
+            Some(c) if c.is_alphabetic() => {
+                let mut word = String::new();
+                while self.chars.peek().map_or(false, |c| c.is_alphabetic()) {
+                    word.push(self.chars.next().unwrap());
+                }
+                match word.as_str() {
+                    "true"  => Ok(Json::Bool(true)),
+                    "false" => Ok(Json::Bool(false)),
+                    _ => Err(format_err!("unexpected JSON literal: {}", word))
+                }
+            },
+            Some(c) => Err(format_err!("unexpected character in JSON: {}", c)),
+            None => Err(format_err!("unexpected end of JSON"))
+        }
+    }
+
+    fn parse_string(&mut self) -> Result<String, Error> {
+        self.expect('"')?;
+        let mut result = String::new();
+        loop {
+            match self.chars.next() {
+                Some('"') => return Ok(result),
+                Some('\\') => match self.chars.next() {
+                    Some(c @ '"') | Some(c @ '\\') | Some(c @ '/') => {
+                        result.push(c)
+                    },
+                    _ => return Err(format_err!("unsupported JSON escape"))
+                },
+                Some(c) => result.push(c),
+                None => return Err(format_err!("unterminated JSON string"))
+            }
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<Json, Error> {
+        let mut values = Vec::new();
+        self.expect('[')?;
+        self.skip_whitespace();
+        if self.chars.peek() == Some(&']') {
+            self.chars.next();
+            return Ok(Json::Array(values));
+        }
+        loop {
+            values.push(self.parse_value()?);
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some(',') => {},
+                Some(']') => return Ok(Json::Array(values)),
+                _ => return Err(format_err!("expected ',' or ']' in JSON"))
+            }
+        }
+    }
+
+    fn parse_object(&mut self) -> Result<Json, Error> {
+        let mut fields = Vec::new();
+        self.expect('{')?;
+        self.skip_whitespace();
+        if self.chars.peek() == Some(&'}') {
+            self.chars.next();
+            return Ok(Json::Object(fields));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.parse_string()?;
+            self.expect(':')?;
+            fields.push((key, self.parse_value()?));
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some(',') => {},
+                Some('}') => return Ok(Json::Object(fields)),
+                _ => return Err(format_err!("expected ',' or '}}' in JSON"))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Snapshot;
+    use util::duet::{Instruction,MessageQueue,Program};
+    use util::duet::Instruction::*;
+    use util::duet::Operand::*;
+
+    // Counts a up to 10, sending each value.
+    fn counter() -> Vec<Instruction> {
+        vec![Add(Register('a'), Value(1)),
+             Send(Register('a')),
+             Set(Register('b'), Register('a')),
+             Add(Register('b'), Value(-10)),
+             JumpNZ(Register('b'), Value(-4))]
+    }
+
+    #[test]
+    fn restore_resumes_where_snapshot_was_taken() {
+        let instructions = counter();
+        let mut program = Program::new(0, &instructions);
+        for _ in 0..12 {
+            program.step().unwrap();
+        }
+        let snapshot = program.snapshot();
+        while program.is_running() {
+            program.step().unwrap();
+        }
+
+        let mut resumed = Program::new(0, &instructions);
+        resumed.restore(&snapshot).unwrap();
+        assert_eq!(resumed.read_register('a'), 3);
+        assert_eq!(resumed.steps(), 12);
+        while resumed.is_running() {
+            resumed.step().unwrap();
+        }
+        assert_eq!(resumed.snapshot(), program.snapshot());
+    }
+
+    #[test]
+    fn text_and_json_round_trip() {
+        let instructions = counter();
+        let mut program = Program::new(1, &instructions);
+        program.add_to_queue(5);
+        program.add_to_queue(-3);
+        for _ in 0..7 {
+            program.step().unwrap();
+        }
+        let snapshot = program.snapshot();
+
+        let text = snapshot.to_text();
+        assert!(text.contains("steps 7\n"));
+        assert!(text.contains("io queue 5 -3\n"));
+        assert_eq!(Snapshot::<MessageQueue>::from_text(&text).unwrap(),
+                   snapshot);
+        let json = snapshot.to_json();
+        assert_eq!(Snapshot::<MessageQueue>::from_json(&json).unwrap(),
+                   snapshot);
+    }
+
+    #[test]
+    fn bad_snapshots_are_rejected() {
+        assert!(Snapshot::<MessageQueue>::from_text("pc 3\n").is_err());
+        assert!(Snapshot::<MessageQueue>::from_json("{\"pc\":3").is_err());
+
+        let instructions = counter();
+        let mut program = Program::new(0, &instructions);
+        let mut snapshot = program.snapshot();
+        snapshot.pc = 100;
+        assert!(program.restore(&snapshot).is_err());
+    }
+}
@@ -0,0 +1,535 @@
+use failure::Error;
+use std::collections::{BTreeMap,HashMap};
+use std::fs::File;
+use std::io::{self,BufRead,BufWriter,Read,Write};
+use super::{asm,Arithmetic,Dialect,Instruction,Operand,Program};
+
+// One executed instruction: where it was, what it was, and which registers it
+// changed, as (register, old value, new value).
+#[derive(Clone,Debug,PartialEq)]
+pub struct TraceEvent {
+    pub pc: usize,
+    pub instruction: String,
+    pub changes: Vec<(char, isize, isize)>
+}
+
+// Somewhere to send a program's execution trace. `start` is called once with
+// the registers' values when the sink is attached, then `record` once for
+// every instruction executed.
+pub trait TraceSink {
+    fn start(&mut self, registers: &[(char, isize)]) -> Result<(), Error>;
+    fn record(&mut self, event: &TraceEvent) -> Result<(), Error>;
+}
+
+impl<'a, S: TraceSink + ?Sized> TraceSink for &'a mut S {
+    fn start(&mut self, registers: &[(char, isize)]) -> Result<(), Error> {
+        (**self).start(registers)
+    }
+
+    fn record(&mut self, event: &TraceEvent) -> Result<(), Error> {
+        (**self).record(event)
+    }
+}
+
+// Writes a trace as text, one line per event with tab-separated fields. The
+// first line holds the starting registers:
+//
+//   init    p:0
+//   0       set i 31        i:0:31
+//   1       set a 1         a:0:1
+//   2       mul p 17
+//
+// Each event line is the pc, the instruction, then any register changes as
+// register:old:new.
+pub struct TraceWriter<W: Write> {
+    writer: W
+}
+
+impl<W: Write> TraceWriter<W> {
+    pub fn new(writer: W) -> Self {
+        TraceWriter {
+            writer: writer
+        }
+    }
+}
+
+impl TraceWriter<BufWriter<File>> {
+    pub fn create(path: &str) -> Result<Self, Error> {
+        Ok(TraceWriter::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+impl<W: Write> TraceSink for TraceWriter<W> {
+    fn start(&mut self, registers: &[(char, isize)]) -> Result<(), Error> {
+        let values = registers.iter()
+                              .map(|&(r, v)| format!("{}:{}", r, v))
+                              .collect::<Vec<String>>();
+        writeln!(self.writer, "init\t{}", values.join(" "))?;
+        Ok(())
+    }
+
+    fn record(&mut self, event: &TraceEvent) -> Result<(), Error> {
+        let changes = event.changes
+                           .iter()
+                           .map(|&(r, old, new)| format!("{}:{}:{}",
+                                                         r, old, new))
+                           .collect::<Vec<String>>();
+        writeln!(self.writer, "{}\t{}\t{}",
+                 event.pc, event.instruction, changes.join(" "))?;
+        Ok(())
+    }
+}
+
+// The register an instruction writes to, if any.
+pub fn destination(inst: Instruction) -> Option<char> {
+    match inst {
+        Instruction::Add(Operand::Register(r), _) |
+        Instruction::Mod(Operand::Register(r), _) |
+        Instruction::Multiply(Operand::Register(r), _) |
+        Instruction::Receive(Operand::Register(r)) |
+        Instruction::Set(Operand::Register(r), _) |
+        Instruction::Sub(Operand::Register(r), _) |
+        Instruction::Divide(Operand::Register(r), _) |
+        Instruction::Pop(Operand::Register(r)) => Some(r),
+        _ => None
+    }
+}
+
+// A recorded trace that can be stepped through in either direction,
+// reconstructing register values as it goes.
+pub struct Replay {
+    initial: BTreeMap<char, isize>,
+    events: Vec<TraceEvent>,
+    registers: BTreeMap<char, isize>,
+    position: usize
+}
+
+impl Replay {
+    pub fn new(initial: Vec<(char, isize)>, events: Vec<TraceEvent>) -> Self {
+        let initial: BTreeMap<char, isize> = initial.into_iter().collect();
+        Replay {
+            registers: initial.clone(),
+            initial: initial,
+            events: events,
+            position: 0
+        }
+    }
+
+    pub fn load(path: &str) -> Result<Self, Error> {
+        let mut text = String::new();
+        File::open(path)?.read_to_string(&mut text)?;
+        Replay::parse(&text)
+    }
+
+    // Read a trace in the format written by TraceWriter.
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let mut lines = text.lines();
+        let initial = match lines.next() {
+            Some(line) if line.starts_with("init\t") => {
+                let mut registers = Vec::new();
+                for field in line[5..].split_whitespace() {
+                    let parts = field.split(':').collect::<Vec<&str>>();
+                    if parts.len() != 2 {
+                        return Err(format_err!("bad trace init: {}", line));
+                    }
+                    registers.push((parse_register(parts[0])?,
+                                    parts[1].parse::<isize>()?));
+                }
+                registers
+            },
+            _ => return Err(format_err!("trace is missing its init line"))
+        };
+        let mut events = Vec::new();
+        for line in lines {
+            let fields = line.split('\t').collect::<Vec<&str>>();
+            if fields.len() != 3 {
+                return Err(format_err!("bad trace line: {}", line));
+            }
+            let mut changes = Vec::new();
+            for change in fields[2].split_whitespace() {
+                let parts = change.split(':').collect::<Vec<&str>>();
+                if parts.len() != 3 {
+                    return Err(format_err!("bad trace line: {}", line));
+                }
+                changes.push((parse_register(parts[0])?,
+                              parts[1].parse::<isize>()?,
+                              parts[2].parse::<isize>()?));
+            }
+            events.push(TraceEvent {
+                pc: fields[0].parse::<usize>()?,
+                instruction: fields[1].to_string(),
+                changes: changes
+            });
+        }
+        Ok(Replay::new(initial, events))
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    // Number of events applied so far.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn event(&self, index: usize) -> &TraceEvent {
+        &self.events[index]
+    }
+
+    // Register values after the events applied so far.
+    pub fn registers(&self) -> &BTreeMap<char, isize> {
+        &self.registers
+    }
+
+    // Apply the next event, returning it, or None at the end of the trace.
+    pub fn forward(&mut self) -> Option<&TraceEvent> {
+        if self.position >= self.events.len() {
+            return None;
+        }
+        for &(r, _, new) in &self.events[self.position].changes {
+            self.registers.insert(r, new);
+        }
+        self.position += 1;
+        Some(&self.events[self.position-1])
+    }
+
+    // Undo the last applied event, returning it, or None at the start.
+    pub fn backward(&mut self) -> Option<&TraceEvent> {
+        if self.position == 0 {
+            return None;
+        }
+        self.position -= 1;
+        for &(r, old, _) in &self.events[self.position].changes {
+            self.registers.insert(r, old);
+        }
+        Some(&self.events[self.position])
+    }
+
+    // Move to the given position (clamped to the end of the trace).
+    pub fn seek(&mut self, position: usize) {
+        if position < self.position / 2 {
+            self.registers = self.initial.clone();
+            self.position = 0;
+        }
+        while self.position < position && self.forward().is_some() {}
+        while self.position > position && self.backward().is_some() {}
+    }
+
+    // Index of the most recent event before the current position that changed
+    // register `r`.
+    pub fn last_change(&self, r: char) -> Option<usize> {
+        (0..self.position).rev()
+                          .find(|&i| self.events[i]
+                                         .changes
+                                         .iter()
+                                         .any(|&(changed, _, _)| changed == r))
+    }
+
+    // Index of the first event at which two traces are at different pcs or
+    // leave the registers with different values, or None if they never do.
+    // The instructions' text isn't compared, so a rewritten program only
+    // diverges from the original where it computes something different.
+    pub fn divergence(&self, other: &Replay) -> Option<usize> {
+        let mut ours = nonzero(&self.initial);
+        let mut theirs = nonzero(&other.initial);
+        if ours != theirs {
+            return Some(0);
+        }
+        let events = self.events.iter().zip(other.events.iter());
+        for (i, (a, b)) in events.enumerate() {
+            apply_nonzero(&mut ours, a);
+            apply_nonzero(&mut theirs, b);
+            if a.pc != b.pc || ours != theirs {
+                return Some(i);
+            }
+        }
+        if self.events.len() == other.events.len() {
+            None
+        } else {
+            Some(self.events.len().min(other.events.len()))
+        }
+    }
+}
+
+// Registers that are zero are left out of traces' init lines, but can show
+// up as the result of a change, so divergence only compares nonzero values.
+fn nonzero(registers: &BTreeMap<char, isize>) -> BTreeMap<char, isize> {
+    registers.iter()
+             .filter(|&(_, &v)| v != 0)
+             .map(|(&r, &v)| (r, v))
+             .collect()
+}
+
+fn apply_nonzero(registers: &mut BTreeMap<char, isize>, event: &TraceEvent) {
+    for &(r, _, new) in &event.changes {
+        if new == 0 {
+            registers.remove(&r);
+        } else {
+            registers.insert(r, new);
+        }
+    }
+}
+
+fn parse_register(token: &str) -> Result<char, Error> {
+    let mut chars = token.chars();
+    match (chars.next(), chars.next()) {
+        (Some(r), None) => Ok(r),
+        _ => Err(format_err!("bad register name in trace: {}", token))
+    }
+}
+
+// Run a duet program on its own, writing its trace to `output`. The program
+// may use the extended dialect. The registers named in `initial` start with
+// the given values instead of zero. Fails if the program gets stuck in an
+// infinite loop, or runs for more than `max_steps` instructions if given.
+pub fn record(program_file: &str, output: &str, initial: &[(String, isize)],
+              arithmetic: Arithmetic, max_steps: Option<u64>)
+        -> Result<(), Error> {
+    let assembly = assemble_file(program_file)?;
+    let mut program = Program::new(0, &assembly.instructions);
+    program.set_dialect(Dialect::Extended);
+    program.set_arithmetic(arithmetic);
+    program.set_register_names(&assembly.registers);
+    for &(ref name, value) in initial {
+        program.store(assembly.register(name)?, value);
+    }
+    program.set_trace(TraceWriter::create(output)?)?;
+    program.detect_loops();
+    program.set_step_limit(max_steps);
+    let mut result = Ok(());
+    while program.is_running() {
+        if let Err(e) = program.step() {
+            result = Err(e);
+            break;
+        }
+    }
+    println!("Recorded {} steps to {}", program.steps(), output);
+    result
+}
+
+// Programs are recorded in the extended dialect, so that's how they're
+// assembled for their register names too.
+fn assemble_file(file: &str) -> Result<asm::Assembly, Error> {
+    let mut source = String::new();
+    File::open(file)?.read_to_string(&mut source)?;
+    asm::assemble_dialect(&source, Dialect::Extended)
+        .map_err(|e| format_err!("{}: {}", file, e))
+}
+
+// Interactive trace browser. Reads commands from stdin:
+//
+//   f [n]     step forward n events (default 1)
+//   b [n]     step backward n events (default 1)
+//   g <n>     go to position n
+//   r         print registers
+//   last <r>  find the last change to register r
+//   q         quit
+//
+// If `other` is given, first reports where the two traces diverge and moves
+// to that point. If `program` is given, it's the source the traces were
+// recorded from, and registers go by the names it gives them.
+pub fn browse(path: &str, other: Option<&str>, program: Option<&str>)
+        -> Result<(), Error> {
+    let assembly = match program {
+        Some(file) => Some(assemble_file(file)?),
+        None => None
+    };
+    let names: HashMap<char, String> = match assembly {
+        Some(ref assembly) => assembly.registers
+                                      .iter()
+                                      .map(|(name, &r)| (r, name.clone()))
+                                      .collect(),
+        None => HashMap::new()
+    };
+    let register = |token: &str| match assembly {
+        Some(ref assembly) => assembly.register(token),
+        None => parse_register(token)
+    };
+    let mut replay = Replay::load(path)?;
+    println!("{}: {} events", path, replay.len());
+    if let Some(other_path) = other {
+        let other = Replay::load(other_path)?;
+        match replay.divergence(&other) {
+            None => println!("Traces are identical."),
+            Some(i) => {
+                println!("Traces diverge at event {}:", i);
+                for &(name, trace) in &[(path, &replay), (other_path, &other)] {
+                    if i < trace.len() {
+                        print_event(name, i, trace.event(i), &names);
+                    } else {
+                        println!("  {}: <end of trace>", name);
+                    }
+                }
+                replay.seek(i);
+            }
+        }
+    }
+
+    let stdin = io::stdin();
+    print!("{}> ", replay.position());
+    io::stdout().flush()?;
+    for line in stdin.lock().lines() {
+        let line = line?;
+        let tokens = line.split_whitespace().collect::<Vec<&str>>();
+        let count = match tokens.get(1) {
+            Some(n) => n.parse::<usize>().map_err(|_| n),
+            None => Ok(1)
+        };
+        match (tokens.get(0).cloned(), count) {
+            (Some("f"), Ok(count)) => for _ in 0..count {
+                let position = replay.position();
+                match replay.forward() {
+                    Some(event) => print_event("", position, event, &names),
+                    None => { println!("<end of trace>"); break; }
+                }
+            },
+            (Some("b"), Ok(count)) => for _ in 0..count {
+                match replay.backward() {
+                    Some(_) => {},
+                    None => { println!("<start of trace>"); break; }
+                }
+                let position = replay.position();
+                print_event("undid", position, replay.event(position),
+                            &names);
+            },
+            (Some("g"), Ok(position)) if tokens.len() == 2 => {
+                replay.seek(position)
+            },
+            (Some("f"), Err(n)) |
+            (Some("b"), Err(n)) |
+            (Some("g"), Err(n)) => println!("not a number: {}", n),
+            (Some("r"), _) => {
+                let values = replay.registers()
+                                   .iter()
+                                   .map(|(&r, v)| format!("{}: {}",
+                                                          name(&names, r), v))
+                                   .collect::<Vec<String>>();
+                println!("{}", values.join(", "));
+            },
+            (Some("last"), _) if tokens.len() == 2 => {
+                match register(tokens[1]) {
+                    Ok(r) => match replay.last_change(r) {
+                        Some(i) => print_event("", i, replay.event(i), &names),
+                        None => println!("{} has not changed yet", tokens[1])
+                    },
+                    Err(e) => println!("{}", e)
+                }
+            },
+            (Some("q"), _) => break,
+            (None, _) => {},
+            _ => println!("commands: f [n], b [n], g <n>, r, last <r>, q")
+        }
+        print!("{}> ", replay.position());
+        io::stdout().flush()?;
+    }
+    Ok(())
+}
+
+// A register's name in `names`, or the register itself.
+fn name(names: &HashMap<char, String>, r: char) -> String {
+    names.get(&r).cloned().unwrap_or(r.to_string())
+}
+
+fn print_event(label: &str, index: usize, event: &TraceEvent,
+               names: &HashMap<char, String>) {
+    let changes = event.changes
+                       .iter()
+                       .map(|&(r, old, new)| format!("{}: {} -> {}",
+                                                     name(names, r), old, new))
+                       .collect::<Vec<String>>();
+    println!("  {}[{}] pc {}: {}  {}", label, index, event.pc,
+             event.instruction, changes.join(", "));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Replay,TraceWriter};
+    use util::duet::asm::assemble;
+    use util::duet::{Instruction,Program};
+    use util::duet::Instruction::*;
+    use util::duet::Operand::*;
+
+    fn run_traced(instructions: &Vec<Instruction>) -> Replay {
+        let mut buffer: Vec<u8> = Vec::new();
+        {
+            let mut program = Program::new(0, instructions);
+            program.set_trace(TraceWriter::new(&mut buffer)).unwrap();
+            while program.is_running() {
+                program.step().unwrap();
+            }
+        }
+        Replay::parse(&String::from_utf8(buffer).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn replay_steps_both_ways() {
+        // a counts down from 3 while h counts up.
+        let instructions = vec![Set(Register('a'), Value(3)),
+                                Add(Register('h'), Value(1)),
+                                Sub(Register('a'), Value(1)),
+                                JumpNZ(Register('a'), Value(-2))];
+        let mut replay = run_traced(&instructions);
+        assert_eq!(replay.len(), 10);
+        assert_eq!(replay.registers()[&'p'], 0);
+
+        replay.seek(replay.len());
+        assert_eq!(replay.registers()[&'h'], 3);
+        assert_eq!(replay.registers()[&'a'], 0);
+        assert_eq!(replay.last_change('h'), Some(7));
+        assert_eq!(replay.event(7).pc, 1);
+
+        replay.backward();
+        replay.backward();
+        replay.backward();
+        assert_eq!(replay.registers()[&'a'], 1);
+        assert_eq!(replay.registers()[&'h'], 2);
+        assert_eq!(replay.last_change('h'), Some(4));
+
+        replay.seek(1);
+        assert_eq!(replay.registers()[&'a'], 3);
+        assert!(!replay.registers().contains_key(&'h'));
+    }
+
+    #[test]
+    fn divergence_finds_first_difference() {
+        let original = vec![Set(Register('a'), Value(2)),
+                            Multiply(Register('a'), Value(3)),
+                            Add(Register('a'), Value(1))];
+        let optimized = vec![Set(Register('a'), Value(2)),
+                             Add(Register('a'), Value(4)),
+                             Add(Register('a'), Value(1))];
+        let a = run_traced(&original);
+        let b = run_traced(&optimized);
+        assert_eq!(a.divergence(&a), None);
+        assert_eq!(a.divergence(&b), None);
+
+        let wrong = vec![Set(Register('a'), Value(2)),
+                         Add(Register('a'), Value(5)),
+                         Add(Register('a'), Value(1))];
+        let c = run_traced(&wrong);
+        assert_eq!(a.divergence(&c), Some(1));
+        assert_eq!(a.divergence(&run_traced(&original[..2].to_vec())),
+                   Some(2));
+    }
+    #[test]
+    fn named_registers_keep_their_names() {
+        let assembly = assemble("
+            set count 2
+            mul count 9223372036854775807
+        ").unwrap();
+        let mut buffer: Vec<u8> = Vec::new();
+        let error = {
+            let mut program = Program::new(0, &assembly.instructions);
+            program.set_register_names(&assembly.registers);
+            program.set_trace(TraceWriter::new(&mut buffer)).unwrap();
+            program.step().unwrap();
+            program.step().unwrap_err().to_string()
+        };
+        let replay = Replay::parse(&String::from_utf8(buffer).unwrap())
+                           .unwrap();
+        assert_eq!(replay.event(0).instruction, "set count 2");
+        assert_eq!(error, "overflow in \"mul count 9223372036854775807\" \
+                           at pc 1");
+    }
+}
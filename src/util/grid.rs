@@ -1,6 +1,12 @@
 // Cartesian grid structures and utilities.
 
-#[derive(Clone,Copy,Debug)]
+use std::cmp;
+use std::marker::PhantomData;
+use std::ops::{Add,Index,IndexMut,Mul,Sub};
+use std::slice;
+use std::vec;
+
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
 pub enum Dir {
     Up,
     Right,
@@ -35,9 +41,95 @@ impl Dir {
             &Dir::Left  => Dir::Right,
         }
     }
+
+    // The change in (row, col) from one step this way.
+    #[allow(dead_code)]
+    pub fn unit(&self) -> (isize, isize) {
+        Dir8::from(*self).unit()
+    }
+
+    // The direction a one-step change in (row, col) goes, if it's straight
+    // up, down, left or right.
+    #[allow(dead_code)]
+    pub fn from_unit(unit: (isize, isize)) -> Option<Dir> {
+        match unit {
+            (-1,  0) => Some(Dir::Up),
+            ( 0,  1) => Some(Dir::Right),
+            ( 1,  0) => Some(Dir::Down),
+            ( 0, -1) => Some(Dir::Left),
+            _        => None
+        }
+    }
 }
 
-#[derive(Clone,Copy,Debug)]
+// The four directions and the diagonals between them, clockwise from Up.
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum Dir8 {
+    Up,
+    UpRight,
+    Right,
+    DownRight,
+    Down,
+    DownLeft,
+    Left,
+    UpLeft,
+}
+
+impl Dir8 {
+    pub const ALL: [Dir8; 8] = [Dir8::Up, Dir8::UpRight, Dir8::Right,
+                                Dir8::DownRight, Dir8::Down, Dir8::DownLeft,
+                                Dir8::Left, Dir8::UpLeft];
+    pub const CARDINAL: [Dir8; 4] = [Dir8::Up, Dir8::Right, Dir8::Down,
+                                     Dir8::Left];
+
+    // Turn clockwise by `eighths` times 45 degrees, or anticlockwise if
+    // it's negative.
+    #[allow(dead_code)]
+    pub fn rotate(&self, eighths: isize) -> Dir8 {
+        let turned = (*self as isize + eighths % 8 + 8) % 8;
+        Dir8::ALL[turned as usize]
+    }
+
+    // The change in (row, col) from one step this way.
+    pub fn unit(&self) -> (isize, isize) {
+        match *self {
+            Dir8::Up        => (-1,  0),
+            Dir8::UpRight   => (-1,  1),
+            Dir8::Right     => ( 0,  1),
+            Dir8::DownRight => ( 1,  1),
+            Dir8::Down      => ( 1,  0),
+            Dir8::DownLeft  => ( 1, -1),
+            Dir8::Left      => ( 0, -1),
+            Dir8::UpLeft    => (-1, -1),
+        }
+    }
+
+    // The direction a one-step change in (row, col) goes, if any.
+    #[allow(dead_code)]
+    pub fn from_unit(unit: (isize, isize)) -> Option<Dir8> {
+        Dir8::ALL.iter().cloned().find(|d| d.unit() == unit)
+    }
+
+    // The same direction as a Dir, unless it's diagonal.
+    #[allow(dead_code)]
+    pub fn to_dir(&self) -> Option<Dir> {
+        Dir::from_unit(self.unit())
+    }
+}
+
+impl From<Dir> for Dir8 {
+    fn from(dir: Dir) -> Dir8 {
+        match dir {
+            Dir::Up    => Dir8::Up,
+            Dir::Right => Dir8::Right,
+            Dir::Down  => Dir8::Down,
+            Dir::Left  => Dir8::Left,
+        }
+    }
+}
+
+// Ordered by row, then column.
+#[derive(Clone,Copy,Debug,PartialEq,Eq,PartialOrd,Ord,Hash)]
 pub struct IPos {
     pub row: isize,
     pub col: isize
@@ -66,6 +158,82 @@ impl IPos {
             Dir::Left  => IPos::new(self.row,   self.col-1),
         }
     }
+
+    pub fn neighbor8(&self, dir: Dir8) -> IPos {
+        *self + IPos::from(dir.unit())
+    }
+
+    // The number of up, down, left and right steps to `other`.
+    pub fn manhattan(&self, other: IPos) -> usize {
+        ((self.row - other.row).abs() + (self.col - other.col).abs()) as usize
+    }
+
+    // The number of steps to `other` when diagonal steps are allowed too.
+    #[allow(dead_code)]
+    pub fn chebyshev(&self, other: IPos) -> usize {
+        cmp::max((self.row - other.row).abs(),
+                 (self.col - other.col).abs()) as usize
+    }
+
+    // The positions above, right, below and left of this one.
+    pub fn neighbors4(&self) -> Neighbors {
+        Neighbors {
+            center: *self,
+            dirs: Dir8::CARDINAL.iter()
+        }
+    }
+
+    // The positions around this one, clockwise from above.
+    pub fn neighbors8(&self) -> Neighbors {
+        Neighbors {
+            center: *self,
+            dirs: Dir8::ALL.iter()
+        }
+    }
+}
+
+// (row, col)
+impl From<(isize, isize)> for IPos {
+    fn from(pos: (isize, isize)) -> IPos {
+        IPos::new(pos.0, pos.1)
+    }
+}
+
+impl Add for IPos {
+    type Output = IPos;
+
+    fn add(self, other: IPos) -> IPos {
+        IPos::new(self.row + other.row, self.col + other.col)
+    }
+}
+
+impl Sub for IPos {
+    type Output = IPos;
+
+    fn sub(self, other: IPos) -> IPos {
+        IPos::new(self.row - other.row, self.col - other.col)
+    }
+}
+
+impl Mul<isize> for IPos {
+    type Output = IPos;
+
+    fn mul(self, factor: isize) -> IPos {
+        IPos::new(self.row * factor, self.col * factor)
+    }
+}
+
+pub struct Neighbors {
+    center: IPos,
+    dirs: slice::Iter<'static, Dir8>
+}
+
+impl Iterator for Neighbors {
+    type Item = IPos;
+
+    fn next(&mut self) -> Option<IPos> {
+        self.dirs.next().map(|&dir| self.center.neighbor8(dir))
+    }
 }
 
 macro_rules! ipos {
@@ -73,3 +241,188 @@ macro_rules! ipos {
         IPos::new($row, $col)
     )
 }
+
+// A value at every IPos, each starting out as the same default. Indexing
+// reads a position; indexing mutably writes it, extending bounds().
+pub trait Grid<T>: Index<IPos, Output=T> + IndexMut<IPos> {
+    // The top left and bottom right corners of the smallest rectangle that
+    // holds every position written to so far, or None if nothing has been.
+    fn bounds(&self) -> Option<(IPos, IPos)>;
+
+    // Every position the grid stores a value for and a mutable reference to
+    // that value, in no particular order. Stored positions are all within
+    // bounds(), but grids that only store what's been written to leave the
+    // rest out: iterating never allocates, so this can't write to positions
+    // a grid doesn't store yet.
+    #[allow(dead_code)]
+    fn iter_mut<'a>(&'a mut self) -> vec::IntoIter<(IPos, &'a mut T)>;
+
+    // Every position within bounds() and its value, row by row from the top
+    // left. Positions never written to have the default value.
+    fn iter<'a>(&'a self) -> Iter<'a, Self, T> {
+        Iter {
+            grid: self,
+            bounds: self.bounds(),
+            next: self.bounds().map(|(min, _)| min),
+            value: PhantomData
+        }
+    }
+
+    // How many positions within bounds() have a value matching `predicate`.
+    fn count<F: Fn(&T) -> bool>(&self, predicate: F) -> usize {
+        self.iter().filter(|&(_, v)| predicate(v)).count()
+    }
+}
+
+// The bounds of a grid after writing to `pos`.
+pub fn extend_bounds(bounds: Option<(IPos, IPos)>, pos: IPos)
+        -> Option<(IPos, IPos)> {
+    Some(match bounds {
+        None => (pos, pos),
+        Some((min, max)) => (IPos::new(cmp::min(min.row, pos.row),
+                                       cmp::min(min.col, pos.col)),
+                             IPos::new(cmp::max(max.row, pos.row),
+                                       cmp::max(max.col, pos.col)))
+    })
+}
+
+pub struct Iter<'a, G: 'a + ?Sized, T: 'a> {
+    grid: &'a G,
+    bounds: Option<(IPos, IPos)>,
+    next: Option<IPos>,
+    value: PhantomData<&'a T>
+}
+
+impl<'a, T, G: Grid<T> + ?Sized> Iterator for Iter<'a, G, T> {
+    type Item = (IPos, &'a T);
+
+    fn next(&mut self) -> Option<(IPos, &'a T)> {
+        let pos = self.next?;
+        let (min, max) = self.bounds.unwrap();
+        self.next = if pos.col < max.col {
+            Some(IPos::new(pos.row, pos.col + 1))
+        } else if pos.row < max.row {
+            Some(IPos::new(pos.row + 1, min.col))
+        } else {
+            None
+        };
+        let grid: &'a G = self.grid;
+        Some((pos, &grid[pos]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Dir,Dir8,Grid,IPos};
+    use util::dense_grid::DenseGrid;
+    use util::infinite_grid::InfiniteGrid;
+    use util::sparse_grid::SparseGrid;
+
+    #[test]
+    fn rotations_wrap_both_ways() {
+        assert_eq!(Dir8::Up.rotate(1), Dir8::UpRight);
+        assert_eq!(Dir8::Up.rotate(-1), Dir8::UpLeft);
+        assert_eq!(Dir8::Left.rotate(3), Dir8::UpRight);
+        assert_eq!(Dir8::DownRight.rotate(-20), Dir8::UpLeft);
+        for &dir in &Dir8::ALL {
+            assert_eq!(dir.rotate(8), dir);
+            assert_eq!(dir.rotate(4).unit(),
+                       (-dir.unit().0, -dir.unit().1));
+        }
+    }
+
+    #[test]
+    fn units_round_trip() {
+        for &dir in &[Dir::Up, Dir::Right, Dir::Down, Dir::Left] {
+            assert_eq!(Dir::from_unit(dir.unit()), Some(dir));
+            assert_eq!(Dir8::from(dir).to_dir(), Some(dir));
+            assert_eq!(IPos::origin().neighbor(dir),
+                       IPos::origin().neighbor8(Dir8::from(dir)));
+        }
+        for &dir in &Dir8::ALL {
+            assert_eq!(Dir8::from_unit(dir.unit()), Some(dir));
+        }
+        assert_eq!(Dir8::DownLeft.to_dir(), None);
+        assert_eq!(Dir8::from_unit((2, 0)), None);
+    }
+
+    #[test]
+    fn neighborhoods() {
+        let around: Vec<IPos> = ipos!(5, -5).neighbors8().collect();
+        assert_eq!(around.len(), 8);
+        assert_eq!(around[0], ipos!(4, -5));
+        assert_eq!(around[1], ipos!(4, -4));
+        assert_eq!(around[7], ipos!(4, -6));
+        let adjacent: Vec<IPos> = ipos!(5, -5).neighbors4().collect();
+        assert_eq!(adjacent, vec![ipos!(4, -5), ipos!(5, -4), ipos!(6, -5),
+                                  ipos!(5, -6)]);
+    }
+
+    #[test]
+    fn arithmetic_and_distances() {
+        let a = ipos!(2, -3);
+        let b = IPos::from((-1, 1));
+        assert_eq!(a + b, ipos!(1, -2));
+        assert_eq!(a - b, ipos!(3, -4));
+        assert_eq!(b * 3, ipos!(-3, 3));
+        assert_eq!(a.manhattan(b), 7);
+        assert_eq!(a.chebyshev(b), 4);
+        assert_eq!(a.manhattan(a), 0);
+        let mut positions = vec![ipos!(1, 0), ipos!(0, 5), ipos!(0, -1)];
+        positions.sort();
+        assert_eq!(positions, vec![ipos!(0, -1), ipos!(0, 5), ipos!(1, 0)]);
+    }
+
+    // Every implementation must behave like the others.
+    fn exercise<G: Grid<char>>(mut grid: G) {
+        assert!(grid.bounds().is_none());
+        assert_eq!(grid[ipos!(-1000, 545)], '.');
+        assert_eq!(grid.iter_mut().count(), 0);
+
+        // Either side of tile edges, and within one tile.
+        grid[ipos!(-65, 63)] = 'a';
+        grid[ipos!(-64, 64)] = 'b';
+        grid[ipos!(0, -1)] = 'c';
+        grid[ipos!(0, 0)] = 'd';
+        grid[ipos!(0, 0)] = 'e';
+        assert_eq!(grid[ipos!(-65, 63)], 'a');
+        assert_eq!(grid[ipos!(-64, 64)], 'b');
+        assert_eq!(grid[ipos!(0, -1)], 'c');
+        assert_eq!(grid[ipos!(0, 0)], 'e');
+        assert_eq!(grid[ipos!(-64, 63)], '.');
+
+        let (min, max) = grid.bounds().unwrap();
+        assert_eq!((min.row, min.col, max.row, max.col), (-65, -1, 0, 64));
+        assert_eq!(grid.iter().count(), 66 * 66);
+        assert_eq!(grid.count(|&c| c != '.'), 4);
+        let first = grid.iter().next().unwrap().0;
+        assert_eq!((first.row, first.col), (-65, -1));
+
+        let mut written: Vec<IPos> = Vec::new();
+        for (pos, c) in grid.iter_mut() {
+            if *c != '.' {
+                *c = c.to_ascii_uppercase();
+                written.push(pos);
+            }
+        }
+        written.sort();
+        assert_eq!(written, vec![ipos!(-65, 63), ipos!(-64, 64),
+                                 ipos!(0, -1), ipos!(0, 0)]);
+        assert_eq!(grid[ipos!(-64, 64)], 'B');
+        let row: String = grid.iter()
+                              .filter(|&(pos, _)| pos.row == 0)
+                              .map(|(_, &c)| c)
+                              .collect();
+        assert_eq!(&row[..3], "CE.");
+        assert_eq!(row.len(), 66);
+        let (min, max) = grid.bounds().unwrap();
+        assert_eq!((min.row, min.col, max.row, max.col), (-65, -1, 0, 64));
+    }
+
+    #[test]
+    fn implementations_agree() {
+        exercise(InfiniteGrid::new('.'));
+        exercise(DenseGrid::new('.'));
+        exercise(SparseGrid::new('.'));
+    }
+}
@@ -0,0 +1,412 @@
+// Hexagonal grids of flat-topped hexes, as in day 11.
+
+use failure::Error;
+use std::fmt;
+use std::ops::{Add,Index,IndexMut,Mul,Sub};
+use std::str::FromStr;
+use util::grid::{self,Grid,IPos};
+use util::infinite_grid::InfiniteGrid;
+
+// The six neighboring hexes, clockwise from north.
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum Direction {
+    N,
+    NE,
+    SE,
+    S,
+    SW,
+    NW
+}
+
+#[allow(dead_code)]
+impl Direction {
+    pub const ALL: [Direction; 6] = [Direction::N, Direction::NE,
+                                     Direction::SE, Direction::S,
+                                     Direction::SW, Direction::NW];
+
+    // Turn clockwise by `sixths` times 60 degrees, or anticlockwise if it's
+    // negative.
+    pub fn rotate(&self, sixths: isize) -> Direction {
+        let turned = (*self as isize + sixths % 6 + 6) % 6;
+        Direction::ALL[turned as usize]
+    }
+
+    pub fn reverse(&self) -> Direction {
+        self.rotate(3)
+    }
+
+    // The offset of the neighbor in this direction.
+    pub fn unit(&self) -> HexPoint {
+        match *self {
+            Direction::N  => HexPoint::new( 0,  1),
+            Direction::NE => HexPoint::new( 1,  0),
+            Direction::SE => HexPoint::new( 1, -1),
+            Direction::S  => HexPoint::new( 0, -1),
+            Direction::SW => HexPoint::new(-1,  0),
+            Direction::NW => HexPoint::new(-1,  1)
+        }
+    }
+}
+
+impl FromStr for Direction {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "n"  => Ok(Direction::N),
+            "ne" => Ok(Direction::NE),
+            "se" => Ok(Direction::SE),
+            "s"  => Ok(Direction::S),
+            "sw" => Ok(Direction::SW),
+            "nw" => Ok(Direction::NW),
+            _    => Err(format_err!("bad direction: {}", s))
+        }
+    }
+}
+
+// Axial-coordinate point in hex space.
+// For what it's worth, I'm choosing these axes:
+//
+//   +y           -z
+//      ↖ _____ ↗
+//       ╱     ╲
+//      ╱       ╲
+// -x ← ╲       ╱ →  +x
+//       ╲_____╱
+//      ↙       ↘
+//    +z          -y
+//
+// z will always be calculated from x and y, so x + y + z == 0 and the three
+// together are the point's cube coordinates.
+#[derive(Clone,Copy,Debug,PartialEq,Eq,PartialOrd,Ord,Hash)]
+pub struct HexPoint {
+    pub x: isize,
+    pub y: isize,
+    pub z: isize
+}
+
+#[allow(dead_code)]
+impl HexPoint {
+    pub fn new(x: isize, y: isize) -> Self {
+        HexPoint {
+            x: x,
+            y: y,
+            z: 0 - x - y
+        }
+    }
+
+    pub fn origin() -> Self {
+        HexPoint {
+            x: 0,
+            y: 0,
+            z: 0
+        }
+    }
+
+    // The point with cube coordinates (x, y, z), which must sum to zero.
+    pub fn from_cube(x: isize, y: isize, z: isize) -> Option<Self> {
+        if x + y + z == 0 {
+            Some(HexPoint::new(x, y))
+        } else {
+            None
+        }
+    }
+
+    pub fn neighbor(&self, dir: Direction) -> HexPoint {
+        *self + dir.unit()
+    }
+
+    // All six neighbors, clockwise from north.
+    pub fn neighbors(&self) -> Vec<HexPoint> {
+        Direction::ALL.iter().map(|&dir| self.neighbor(dir)).collect()
+    }
+
+    // The number of steps to `other`.
+    pub fn manhattan_distance(&self, other: &HexPoint) -> usize {
+        let cube_distance: isize = (self.x-other.x).abs() +
+                                   (self.y-other.y).abs() +
+                                   (self.z-other.z).abs();
+        (cube_distance as usize)/2
+    }
+
+    // Turn about the origin by `sixths` times 60 degrees clockwise, or
+    // anticlockwise if it's negative.
+    pub fn rotate(&self, sixths: isize) -> HexPoint {
+        let mut point = *self;
+        for _ in 0..(sixths % 6 + 6) % 6 {
+            point = HexPoint::new(-point.z, -point.x);
+        }
+        point
+    }
+
+    // Turn about `center` instead.
+    pub fn rotate_around(&self, center: HexPoint, sixths: isize) -> HexPoint {
+        center + (*self - center).rotate(sixths)
+    }
+
+    // The hexes `radius` steps from this one, clockwise from the one that
+    // far south west. Radius 0 is just this hex.
+    pub fn ring(&self, radius: usize) -> Vec<HexPoint> {
+        if radius == 0 {
+            return vec![*self];
+        }
+        let mut ring = Vec::with_capacity(6 * radius);
+        let mut point = *self + Direction::SW.unit() * radius as isize;
+        for &dir in &Direction::ALL {
+            for _ in 0..radius {
+                ring.push(point);
+                point = point.neighbor(dir);
+            }
+        }
+        ring
+    }
+
+    // The hexes within `radius` steps, ring by ring outwards.
+    pub fn spiral(&self, radius: usize) -> Vec<HexPoint> {
+        (0..radius + 1).flat_map(|r| self.ring(r)).collect()
+    }
+
+    // The hexes along a straight line to `other`, both ends included, each
+    // a neighbor of the one before.
+    pub fn line(&self, other: HexPoint) -> Vec<HexPoint> {
+        let steps = self.manhattan_distance(&other);
+        // Nudged slightly, so that points exactly between two hexes always
+        // fall the same way.
+        let from = (self.x as f64 + 1e-6, self.y as f64 + 1e-6,
+                    self.z as f64 - 2e-6);
+        (0..steps + 1).map(|i| {
+            let t = if steps == 0 { 0.0 } else { i as f64 / steps as f64 };
+            cube_round(from.0 + (other.x as f64 - from.0) * t,
+                       from.1 + (other.y as f64 - from.1) * t,
+                       from.2 + (other.z as f64 - from.2) * t)
+        }).collect()
+    }
+
+    // The center of this hex in pixels, for hexes `size` pixels from center
+    // to corner with the origin's center at (0, 0). Pixel y grows
+    // downwards, so north is up.
+    pub fn to_pixel(&self, size: f64) -> (f64, f64) {
+        (size * 1.5 * self.x as f64,
+         -size * 3f64.sqrt() * (self.y as f64 + self.x as f64 / 2.0))
+    }
+
+    // The hex covering a pixel: the inverse of to_pixel.
+    pub fn from_pixel(pixel: (f64, f64), size: f64) -> HexPoint {
+        let x = pixel.0 / (size * 1.5);
+        let y = -pixel.1 / (size * 3f64.sqrt()) - x / 2.0;
+        cube_round(x, y, -x - y)
+    }
+}
+
+// The hex whose cube coordinates are nearest to fractional ones.
+fn cube_round(x: f64, y: f64, z: f64) -> HexPoint {
+    let (rx, ry, rz) = (x.round(), y.round(), z.round());
+    let (dx, dy, dz) = ((rx - x).abs(), (ry - y).abs(), (rz - z).abs());
+    // Rounding all three can break x + y + z == 0; recompute the one that
+    // moved furthest.
+    if dx > dy && dx > dz {
+        HexPoint::new((-ry - rz) as isize, ry as isize)
+    } else if dy > dz {
+        HexPoint::new(rx as isize, (-rx - rz) as isize)
+    } else {
+        HexPoint::new(rx as isize, ry as isize)
+    }
+}
+
+impl fmt::Display for HexPoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<HexPoint ({}, {}, {})>", self.x, self.y, self.z)
+    }
+}
+
+impl Add for HexPoint {
+    type Output = HexPoint;
+
+    fn add(self, other: HexPoint) -> HexPoint {
+        HexPoint::new(self.x + other.x, self.y + other.y)
+    }
+}
+
+impl Sub for HexPoint {
+    type Output = HexPoint;
+
+    fn sub(self, other: HexPoint) -> HexPoint {
+        HexPoint::new(self.x - other.x, self.y - other.y)
+    }
+}
+
+impl Mul<isize> for HexPoint {
+    type Output = HexPoint;
+
+    fn mul(self, factor: isize) -> HexPoint {
+        HexPoint::new(self.x * factor, self.y * factor)
+    }
+}
+
+// An infinite hex grid where every hex has default value until it is set
+// otherwise. Stored as an InfiniteGrid with x as the column and y as the
+// row.
+#[derive(Clone)]
+pub struct InfiniteHexGrid<T: Clone> {
+    grid: InfiniteGrid<T>
+}
+
+#[allow(dead_code)]
+impl<T: Clone> InfiniteHexGrid<T> {
+    pub fn new(default: T) -> InfiniteHexGrid<T> {
+        InfiniteHexGrid {
+            grid: InfiniteGrid::new(default)
+        }
+    }
+
+    fn pos(point: HexPoint) -> IPos {
+        ipos!(point.y, point.x)
+    }
+
+    // Every hex written to so far, with its value, and some that weren't
+    // with the default: those within the same range of x and y.
+    pub fn iter<'a>(&'a self) -> Iter<'a, T> {
+        Iter {
+            cells: self.grid.iter()
+        }
+    }
+
+    // How many of the hexes iter() visits have a value matching
+    // `predicate`.
+    pub fn count<F: Fn(&T) -> bool>(&self, predicate: F) -> usize {
+        self.grid.count(predicate)
+    }
+}
+
+pub struct Iter<'a, T: 'a + Clone> {
+    cells: grid::Iter<'a, InfiniteGrid<T>, T>
+}
+
+impl<'a, T: Clone> Iterator for Iter<'a, T> {
+    type Item = (HexPoint, &'a T);
+
+    fn next(&mut self) -> Option<(HexPoint, &'a T)> {
+        self.cells
+            .next()
+            .map(|(pos, value)| (HexPoint::new(pos.col, pos.row), value))
+    }
+}
+
+impl<T: Clone> Index<HexPoint> for InfiniteHexGrid<T> {
+    type Output = T;
+
+    fn index<'a>(&'a self, point: HexPoint) -> &'a T {
+        &self.grid[Self::pos(point)]
+    }
+}
+
+impl<T: Clone> IndexMut<HexPoint> for InfiniteHexGrid<T> {
+    fn index_mut<'a>(&'a mut self, point: HexPoint) -> &'a mut T {
+        &mut self.grid[Self::pos(point)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Direction,HexPoint,InfiniteHexGrid};
+
+    fn origin() -> HexPoint {
+        HexPoint::origin()
+    }
+
+    #[test]
+    fn directions() {
+        for (i, &dir) in Direction::ALL.iter().enumerate() {
+            let next = Direction::ALL[(i + 1) % 6];
+            assert_eq!(dir.rotate(1), next);
+            assert_eq!(next.rotate(-1), dir);
+            assert_eq!(dir.rotate(6), dir);
+            assert_eq!(dir.unit().rotate(1), next.unit());
+            assert_eq!(dir.reverse().unit(), dir.unit() * -1);
+            assert_eq!(dir.unit().manhattan_distance(&origin()), 1);
+        }
+        assert_eq!("se".parse::<Direction>().unwrap(), Direction::SE);
+        assert!("e".parse::<Direction>().is_err());
+    }
+
+    #[test]
+    fn arithmetic_and_rotation() {
+        let p = HexPoint::new(3, -1);
+        assert_eq!(p.z, -2);
+        assert_eq!(HexPoint::from_cube(3, -1, -2), Some(p));
+        assert_eq!(HexPoint::from_cube(3, -1, 0), None);
+        assert_eq!(p - p, origin());
+        assert_eq!(p.manhattan_distance(&origin()), 3);
+        assert_eq!(p.rotate(1), HexPoint::new(2, -3));
+        assert_eq!(p.rotate(-5), p.rotate(1));
+        assert_eq!(p.rotate(3), p * -1);
+        let center = HexPoint::new(1, 1);
+        assert_eq!(center.neighbor(Direction::N)
+                         .rotate_around(center, 2),
+                   center.neighbor(Direction::SE));
+        assert_eq!(format!("{}", p), "<HexPoint (3, -1, -2)>");
+    }
+
+    #[test]
+    fn rings_and_spirals() {
+        let center = HexPoint::new(-2, 5);
+        assert_eq!(center.ring(0), vec![center]);
+        // Starting from the south west one.
+        let mut neighbors = center.neighbors();
+        neighbors.rotate_left(4);
+        assert_eq!(center.ring(1), neighbors);
+        let ring = center.ring(3);
+        assert_eq!(ring.len(), 18);
+        for (i, p) in ring.iter().enumerate() {
+            assert_eq!(p.manhattan_distance(&center), 3);
+            assert_eq!(p.manhattan_distance(&ring[(i + 1) % 18]), 1);
+        }
+        let spiral = center.spiral(3);
+        assert_eq!(spiral.len(), 1 + 6 + 12 + 18);
+        let mut distinct = spiral.clone();
+        distinct.sort();
+        distinct.dedup();
+        assert_eq!(distinct.len(), spiral.len());
+    }
+
+    #[test]
+    fn lines() {
+        let a = HexPoint::new(-2, 0);
+        let b = HexPoint::new(3, 2);
+        let line = a.line(b);
+        assert_eq!(line.len(), a.manhattan_distance(&b) + 1);
+        assert_eq!((line[0], line[line.len() - 1]), (a, b));
+        for pair in line.windows(2) {
+            assert_eq!(pair[0].manhattan_distance(&pair[1]), 1);
+        }
+        assert_eq!(a.line(a), vec![a]);
+        let straight = origin().line(HexPoint::new(0, 3));
+        assert!(straight.iter().all(|p| p.x == 0));
+    }
+
+    #[test]
+    fn pixels() {
+        let (x, y) = Direction::N.unit().to_pixel(10.0);
+        assert!(x.abs() < 1e-9 && (y + 10.0 * 3f64.sqrt()).abs() < 1e-9);
+        for p in origin().spiral(4) {
+            let (px, py) = p.to_pixel(7.5);
+            assert_eq!(HexPoint::from_pixel((px, py), 7.5), p);
+            // Anywhere well inside the hex maps back to it too.
+            assert_eq!(HexPoint::from_pixel((px + 3.0, py - 3.0), 7.5), p);
+        }
+    }
+
+    #[test]
+    fn grid() {
+        let mut grid = InfiniteHexGrid::new(false);
+        for p in HexPoint::new(1, 1).ring(2) {
+            grid[p] = true;
+        }
+        assert!(grid[HexPoint::new(-1, 1)]);
+        assert!(!grid[HexPoint::new(1, 1)]);
+        assert_eq!(grid.count(|&b| b), 12);
+        assert_eq!(grid.iter().filter(|&(_, &b)| b).count(), 12);
+        let (first, _) = grid.iter().next().unwrap();
+        assert_eq!((first.x, first.y), (-1, -1));
+    }
+}
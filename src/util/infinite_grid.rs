@@ -1,47 +1,108 @@
-use util::grid::IPos;
+use std::cell::Cell;
+use std::collections::HashMap;
 use std::ops::{Index,IndexMut};
+use std::vec;
+use util::grid::{extend_bounds,Grid,IPos};
+
+const TILE_BITS: usize = 6;
+const TILE_SIZE: usize = 1 << TILE_BITS;
+const TILE_MASK: isize = TILE_SIZE as isize - 1;
 
 /* An infinite 2-dimensional grid where every position has default value until
  * it is set otherwise. */
+//
+// Cells are stored in square tiles of TILE_SIZE cells a side, allocated the
+// first time something in them is written to, so growing in any direction
+// costs one more tile and never moves what's already there.
 #[derive(Clone)]
 pub struct InfiniteGrid<T: Clone> {
-    rows: Vec<Vec<T>>,
-    default: T
+    // Each tile's cells, row by row, and the tile's position in tile space.
+    tiles: Vec<Vec<T>>,
+    corners: Vec<IPos>,
+    // Where in `tiles` each allocated tile is.
+    index: HashMap<IPos, usize>,
+    // The tile last looked up and where it is. Walks tend to stay within a
+    // tile for a while, and this saves hashing its position every time.
+    last: Cell<Option<(IPos, usize)>>,
+    default: T,
+    // The smallest and largest row and column written to, if any.
+    bounds: Option<(IPos, IPos)>
 }
 
 impl<T: Clone> InfiniteGrid<T> {
     pub fn new(default: T) -> InfiniteGrid<T> {
         InfiniteGrid {
-            rows: Vec::new(),
-            default: default
+            tiles: Vec::new(),
+            corners: Vec::new(),
+            index: HashMap::new(),
+            last: Cell::new(None),
+            default: default,
+            bounds: None
         }
     }
 
-    // Ensure the underlying vectors have min_rows and min_cols capacity.
-    fn ensure_capacity(&mut self, min_rows: usize, min_cols: usize) {
-        let orig_num_rows = self.rows.len();
-        if orig_num_rows < min_rows {
-            self.rows.reserve(min_rows - orig_num_rows);
-            for _ in orig_num_rows..min_rows {
-                self.rows.push(Vec::with_capacity(min_cols));
+    // The tile holding `pos`, and its index within that tile. Shifting
+    // rounds towards negative infinity, so negative positions work too.
+    fn locate(pos: IPos) -> (IPos, usize) {
+        let tile = ipos!(pos.row >> TILE_BITS, pos.col >> TILE_BITS);
+        let offset = ((pos.row & TILE_MASK) << TILE_BITS) |
+                     (pos.col & TILE_MASK);
+        (tile, offset as usize)
+    }
+
+    // Where in `tiles` a tile is, if it has been allocated.
+    fn find(&self, tile: IPos) -> Option<usize> {
+        if let Some((last, i)) = self.last.get() {
+            if last == tile {
+                return Some(i);
             }
         }
-        for row in 0..min_rows {
-            if self.rows[row].len() < min_cols {
-                self.rows[row].resize(min_cols, self.default.clone());
-            }
+        let found = self.index.get(&tile).cloned();
+        if let Some(i) = found {
+            self.last.set(Some((tile, i)));
+        }
+        found
+    }
+
+    // Where in `tiles` a tile is, allocating it first if need be.
+    fn find_or_allocate(&mut self, tile: IPos) -> usize {
+        if let Some(i) = self.find(tile) {
+            return i;
         }
+        let i = self.tiles.len();
+        self.tiles.push(vec![self.default.clone(); TILE_SIZE * TILE_SIZE]);
+        self.corners.push(tile);
+        self.index.insert(tile, i);
+        self.last.set(Some((tile, i)));
+        i
+    }
+}
+
+impl<T: Clone> Grid<T> for InfiniteGrid<T> {
+    fn bounds(&self) -> Option<(IPos, IPos)> {
+        self.bounds
     }
 
-    // Map a position (in one dimension) from the infinite (isize range) space
-    // to a natural number index (usize) for the underlying Vec.
-    fn pos_to_index(pos: isize) -> usize {
-        let result = if pos < 0 {
-                         (-pos*2) - 1
-                     } else {
-                         pos * 2
-                     };
-        result as usize
+    // Only the positions in tiles that have been allocated, tile by tile.
+    fn iter_mut<'a>(&'a mut self) -> vec::IntoIter<(IPos, &'a mut T)> {
+        let (min, max) = match self.bounds {
+            Some(bounds) => bounds,
+            None => return Vec::new().into_iter()
+        };
+        let mut cells: Vec<(IPos, &mut T)> = Vec::new();
+        for (tile, values) in self.corners.iter().zip(self.tiles.iter_mut()) {
+            for (offset, value) in values.iter_mut().enumerate() {
+                let pos = ipos!((tile.row << TILE_BITS) +
+                                    (offset >> TILE_BITS) as isize,
+                                (tile.col << TILE_BITS) +
+                                    (offset as isize & TILE_MASK));
+                if pos.row >= min.row && pos.row <= max.row &&
+                   pos.col >= min.col && pos.col <= max.col {
+                    cells.push((pos, value));
+                }
+            }
+        }
+        cells.into_iter()
     }
 }
 
@@ -50,29 +111,28 @@ impl<T: Clone> Index<IPos> for InfiniteGrid<T> {
     type Output = T;
 
     fn index<'a>(&'a self, pos: IPos) -> &'a T {
-        let row_index = Self::pos_to_index(pos.row);
-        let col_index = Self::pos_to_index(pos.col);
-        if self.rows.len() <= row_index ||
-           self.rows[row_index].len() <= col_index {
-            return &self.default
+        let (tile, offset) = Self::locate(pos);
+        match self.find(tile) {
+            Some(i) => &self.tiles[i][offset],
+            None => &self.default
         }
-        &self.rows[row_index][col_index]
     }
 }
 
 // Write into the grid at the position (row, col).
 impl<T: Clone> IndexMut<IPos> for InfiniteGrid<T> {
     fn index_mut<'a>(&'a mut self, pos: IPos) -> &'a mut T {
-        let row_index = Self::pos_to_index(pos.row);
-        let col_index = Self::pos_to_index(pos.col);
-        self.ensure_capacity(row_index+1, col_index+1);
-        &mut self.rows[row_index][col_index]
+        self.bounds = extend_bounds(self.bounds, pos);
+        let (tile, offset) = Self::locate(pos);
+        let i = self.find_or_allocate(tile);
+        &mut self.tiles[i][offset]
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::InfiniteGrid;
+    use util::grid::{Grid,IPos};
 
     #[test]
     fn can_be_constructed_with_int() {
@@ -106,4 +166,50 @@ mod tests {
         grid[ipos!(0, 0)] = 'z';
         assert!(grid[ipos!(0, 0)] == 'z');
     }
+
+    #[test]
+    fn bounds_and_iteration_cover_written_cells() {
+        let mut grid: InfiniteGrid<char> = InfiniteGrid::new('.');
+        assert!(grid.bounds().is_none());
+        assert_eq!(grid.iter().count(), 0);
+        grid[ipos!(-1, 2)] = 'a';
+        grid[ipos!(1, -1)] = 'b';
+        let (min, max) = grid.bounds().unwrap();
+        assert_eq!((min.row, min.col, max.row, max.col), (-1, -1, 1, 2));
+
+        let cells: String = grid.iter().map(|(_, &c)| c).collect();
+        assert_eq!(cells, "...a....b...");
+        let first = grid.iter().next().unwrap().0;
+        assert_eq!((first.row, first.col), (-1, -1));
+        assert_eq!(grid.count(|&c| c == '.'), 10);
+
+        // Only the two tiles written to are visited, and none are added.
+        assert_eq!(grid.iter_mut().count(), 5);
+        for (pos, c) in grid.iter_mut() {
+            if pos.row == 0 {
+                *c = '#';
+            }
+        }
+        assert_eq!(grid.tiles.len(), 2);
+        let cells: String = grid.iter().map(|(_, &c)| c).collect();
+        assert_eq!(cells, "...a#...b...");
+    }
+
+    #[test]
+    fn alternating_between_tiles() {
+        let mut grid: InfiniteGrid<usize> = InfiniteGrid::new(0);
+        // Each access lands in a different tile from the one before, and
+        // some in tiles that don't exist yet.
+        for i in 0..10 {
+            grid[ipos!(0, 63)] += 1;
+            grid[ipos!(0, 64)] += 2;
+            assert_eq!(grid[ipos!(-1, 63)], 0);
+            assert_eq!(grid[ipos!(100 * i, 0)], 0);
+            grid[ipos!(-64, -64)] = i as usize;
+        }
+        assert_eq!(grid[ipos!(0, 63)], 10);
+        assert_eq!(grid[ipos!(0, 64)], 20);
+        assert_eq!(grid[ipos!(-64, -64)], 9);
+        assert_eq!(grid.tiles.len(), 3);
+    }
 }
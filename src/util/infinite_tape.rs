@@ -1,11 +1,13 @@
 use std::ops::{Index,IndexMut};
 
+#[allow(dead_code)]
 #[derive(Clone)]
 pub struct InfiniteTape<T: Clone> {
     pub vec: Vec<T>,
     default: T
 }
 
+#[allow(dead_code)]
 impl<T: Clone> InfiniteTape<T> {
     pub fn new(default: T) -> InfiniteTape<T> {
         InfiniteTape {
@@ -48,3 +50,112 @@ impl<T: Clone> IndexMut<isize> for InfiniteTape<T> {
         &mut self.vec[index]
     }
 }
+
+// An infinite tape of bits, all false until set, packed 64 to a word.
+// Bit `origin` of the words is position 0; the words grow at either end as
+// bits further out are set.
+//
+// A bit can't be borrowed mutably, so writes go through set() rather than
+// IndexMut.
+#[derive(Clone,Default)]
+pub struct BitTape {
+    words: Vec<u64>,
+    origin: usize
+}
+
+// What Index hands out for each bit.
+const BITS: [bool; 2] = [false, true];
+
+impl BitTape {
+    pub fn new() -> BitTape {
+        Default::default()
+    }
+
+    // Where a position's bit is in the words. Positions left of the first
+    // word wrap around to huge indexes, so they fail the same bounds check
+    // as positions right of the last one.
+    fn bit(&self, pos: isize) -> usize {
+        (self.origin as isize).wrapping_add(pos) as usize
+    }
+
+    pub fn get(&self, pos: isize) -> bool {
+        let bit = self.bit(pos);
+        match self.words.get(bit / 64) {
+            Some(&word) => word & (1 << (bit % 64)) != 0,
+            None => false
+        }
+    }
+
+    pub fn set(&mut self, pos: isize, value: bool) {
+        let bit = self.bit(pos);
+        if bit / 64 >= self.words.len() {
+            if !value {
+                return;
+            }
+            self.grow(pos);
+            return self.set(pos, value);
+        }
+        if value {
+            self.words[bit / 64] |= 1 << (bit % 64);
+        } else {
+            self.words[bit / 64] &= !(1 << (bit % 64));
+        }
+    }
+
+    // Make room for `pos`, at least doubling the words so that walking off
+    // either end costs amortized constant time.
+    fn grow(&mut self, pos: isize) {
+        let len = self.words.len();
+        let start = -(self.origin as isize);
+        if pos < start {
+            let needed = ((start - pos + 63) / 64) as usize;
+            let extra = if needed > len { needed } else { len };
+            let mut words = vec![0; extra];
+            words.extend_from_slice(&self.words);
+            self.words = words;
+            self.origin += extra * 64;
+        } else {
+            let needed = self.bit(pos) / 64 + 1;
+            self.words.resize(if needed > len * 2 { needed } else { len * 2 },
+                              0);
+        }
+    }
+
+    // How many bits are set.
+    pub fn count_ones(&self) -> usize {
+        self.words.iter().map(|w| w.count_ones() as usize).sum()
+    }
+}
+
+impl Index<isize> for BitTape {
+    type Output = bool;
+
+    fn index(&self, pos: isize) -> &bool {
+        &BITS[self.get(pos) as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BitTape;
+
+    #[test]
+    fn bits_either_side_of_zero() {
+        let mut tape = BitTape::new();
+        assert!(!tape[12345]);
+        assert_eq!(tape.count_ones(), 0);
+        for &pos in &[-130, -65, -64, -1, 0, 1, 63, 64, 200] {
+            tape.set(pos, true);
+        }
+        assert!(tape[-64] && tape[-1] && tape[0] && tape[64]);
+        assert!(!tape[-2] && !tape[2] && !tape[-63]);
+        assert_eq!(tape.count_ones(), 9);
+
+        tape.set(-65, false);
+        tape.set(64, false);
+        tape.set(100_000, false);
+        assert!(!tape[-65] && !tape[64]);
+        assert_eq!(tape.count_ones(), 7);
+        assert!(tape[-130] && tape[-64] && tape[200]);
+    }
+}
@@ -1,5 +1,13 @@
+pub mod conditional;
 pub mod duet;
 #[macro_use]
 pub mod grid;
+// After grid, for ipos!.
+pub mod components;
+pub mod dense_grid;
+pub mod hex;
 pub mod infinite_grid;
 pub mod infinite_tape;
+pub mod pathfind;
+pub mod render;
+pub mod sparse_grid;
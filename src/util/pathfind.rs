@@ -0,0 +1,217 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap,HashMap,HashSet,VecDeque};
+use util::grid::IPos;
+
+// Searches over IPos, moving up, down, left and right. The grid itself is
+// never seen: closures say which positions can be entered and what moving
+// costs, so these work on an InfiniteGrid, a Vec<Vec<T>> (see `cell`) or no
+// grid at all. On unbounded grids the closures must fence the search in,
+// or a search for an unreachable goal never ends.
+
+// A route from one position to another, both included, and what it cost.
+#[allow(dead_code)]
+#[derive(Clone,Debug,PartialEq)]
+pub struct Path {
+    pub steps: Vec<IPos>,
+    pub cost: usize
+}
+
+// The value at `pos` in a grid stored as rows, or None outside it.
+#[allow(dead_code)]
+pub fn cell<T>(rows: &[Vec<T>], pos: IPos) -> Option<&T> {
+    if pos.row < 0 || pos.col < 0 {
+        return None;
+    }
+    rows.get(pos.row as usize).and_then(|row| row.get(pos.col as usize))
+}
+
+// Follow `previous` back from `end` to the start of the search.
+fn reconstruct(previous: &HashMap<IPos, IPos>, end: IPos) -> Vec<IPos> {
+    let mut steps = vec![end];
+    let mut pos = end;
+    while let Some(&p) = previous.get(&pos) {
+        steps.push(p);
+        pos = p;
+    }
+    steps.reverse();
+    steps
+}
+
+// The shortest path from `start` to the nearest position satisfying
+// `is_goal`, counting moves, through positions `passable` allows. `start`
+// need not be passable itself.
+#[allow(dead_code)]
+pub fn bfs<P, G>(start: IPos, passable: P, is_goal: G) -> Option<Path>
+        where P: Fn(IPos) -> bool, G: Fn(IPos) -> bool {
+    let mut previous: HashMap<IPos, IPos> = HashMap::new();
+    let mut seen: HashSet<IPos> = HashSet::new();
+    let mut queue: VecDeque<IPos> = VecDeque::new();
+    seen.insert(start);
+    queue.push_back(start);
+    while let Some(pos) = queue.pop_front() {
+        if is_goal(pos) {
+            let steps = reconstruct(&previous, pos);
+            let cost = steps.len() - 1;
+            return Some(Path {
+                steps: steps,
+                cost: cost
+            });
+        }
+        for next in pos.neighbors4() {
+            if passable(next) && seen.insert(next) {
+                previous.insert(next, pos);
+                queue.push_back(next);
+            }
+        }
+    }
+    None
+}
+
+// The cheapest path from `start` to a position satisfying `is_goal`.
+// `cost(from, to)` is what moving between neighbors costs, or None if it
+// isn't allowed.
+#[allow(dead_code)]
+pub fn dijkstra<C, G>(start: IPos, cost: C, is_goal: G) -> Option<Path>
+        where C: Fn(IPos, IPos) -> Option<usize>, G: Fn(IPos) -> bool {
+    astar(start, cost, is_goal, |_| 0)
+}
+
+// Like dijkstra, but exploring first where `heuristic` says the goal is
+// near. It finds the cheapest path as long as the heuristic never
+// overestimates the cost still to come, as IPos::manhattan to the goal
+// never does when every move costs at least 1.
+#[allow(dead_code)]
+pub fn astar<C, G, H>(start: IPos, cost: C, is_goal: G, heuristic: H)
+        -> Option<Path>
+        where C: Fn(IPos, IPos) -> Option<usize>, G: Fn(IPos) -> bool,
+              H: Fn(IPos) -> usize {
+    let mut previous: HashMap<IPos, IPos> = HashMap::new();
+    let mut best: HashMap<IPos, usize> = HashMap::new();
+    let mut heap = BinaryHeap::new();
+    best.insert(start, 0);
+    heap.push(Reverse((heuristic(start), start)));
+    while let Some(Reverse((estimate, pos))) = heap.pop() {
+        let so_far = best[&pos];
+        if estimate > so_far + heuristic(pos) {
+            // Pushed before a cheaper way here turned up.
+            continue;
+        }
+        if is_goal(pos) {
+            return Some(Path {
+                steps: reconstruct(&previous, pos),
+                cost: so_far
+            });
+        }
+        for next in pos.neighbors4() {
+            let total = match cost(pos, next) {
+                Some(c) => so_far + c,
+                None => continue
+            };
+            if best.get(&next).map_or(true, |&b| total < b) {
+                best.insert(next, total);
+                previous.insert(next, pos);
+                heap.push(Reverse((total + heuristic(next), next)));
+            }
+        }
+    }
+    None
+}
+
+// Every position reachable from `start` through passable ones, including
+// `start`.
+#[allow(dead_code)]
+pub fn flood_fill<P>(start: IPos, passable: P) -> HashSet<IPos>
+        where P: Fn(IPos) -> bool {
+    let mut seen: HashSet<IPos> = HashSet::new();
+    let mut stack = vec![start];
+    seen.insert(start);
+    while let Some(pos) = stack.pop() {
+        for next in pos.neighbors4() {
+            if passable(next) && seen.insert(next) {
+                stack.push(next);
+            }
+        }
+    }
+    seen
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{astar,bfs,cell,dijkstra,flood_fill};
+    use util::grid::{Grid,IPos};
+    use util::infinite_grid::InfiniteGrid;
+
+    fn maze() -> Vec<Vec<char>> {
+        ["S..#....",
+         ".#.#.##.",
+         ".#...#..",
+         ".####.#.",
+         "......#E"].iter().map(|row| row.chars().collect()).collect()
+    }
+
+    fn open(rows: &[Vec<char>], pos: IPos) -> bool {
+        cell(rows, pos).map_or(false, |&c| c != '#')
+    }
+
+    #[test]
+    fn bfs_on_rows() {
+        let rows = maze();
+        let end = ipos!(4, 7);
+        let path = bfs(ipos!(0, 0), |p| open(&rows, p), |p| p == end).unwrap();
+        assert_eq!(path.cost, 15);
+        assert_eq!(path.steps.len(), 16);
+        assert_eq!(path.steps[0], ipos!(0, 0));
+        assert_eq!(path.steps[15], end);
+        for pair in path.steps.windows(2) {
+            assert_eq!(pair[0].manhattan(pair[1]), 1);
+            assert!(open(&rows, pair[1]));
+        }
+        assert!(bfs(ipos!(0, 0), |p| open(&rows, p), |p| p == ipos!(9, 9))
+                    .is_none());
+    }
+
+    #[test]
+    fn weighted_searches_agree() {
+        // Dots cost 1 to enter and digits their value, on an InfiniteGrid
+        // fenced in to the maze's rectangle.
+        let mut grid = InfiniteGrid::new('#');
+        for (row, line) in ["S..1....",
+                            ".#.9.##.",
+                            ".#...#..",
+                            ".####.#.",
+                            "......#E"].iter().enumerate() {
+            for (col, c) in line.chars().enumerate() {
+                grid[ipos!(row as isize, col as isize)] = c;
+            }
+        }
+        let cost = |_, to: IPos| match grid[to] {
+            '#' => None,
+            c => Some(c.to_digit(10).unwrap_or(1) as usize)
+        };
+        let end = ipos!(4, 7);
+        let cheapest = dijkstra(ipos!(0, 0), &cost, |p| p == end).unwrap();
+        // Through the 1 rather than round the bottom.
+        assert_eq!(cheapest.cost, 11);
+        assert_eq!(cheapest.steps[3], ipos!(0, 3));
+        let guided = astar(ipos!(0, 0), &cost, |p| p == end,
+                           |p| p.manhattan(end)).unwrap();
+        assert_eq!(guided, cheapest);
+        // Searching only reads, so the grid didn't grow.
+        assert_eq!(grid.bounds().unwrap().1, end);
+    }
+
+    #[test]
+    fn flood_fill_stays_inside_walls() {
+        let rows = maze();
+        let reachable = flood_fill(ipos!(0, 0), |p| open(&rows, p));
+        let open_cells = rows.iter()
+                             .flat_map(|row| row.iter())
+                             .filter(|&&c| c != '#')
+                             .count();
+        assert_eq!(reachable.len(), open_cells);
+        let walled = flood_fill(ipos!(0, 7), |p| {
+            open(&rows, p) && p.col >= 7
+        });
+        assert_eq!(walled.len(), 5);
+    }
+}
@@ -0,0 +1,253 @@
+use failure::Error;
+use std::fs::File;
+use std::io::{BufWriter,Write};
+use util::grid::{Grid,IPos};
+
+// Drawing grids as text or as Netpbm images, which most image viewers open
+// and which are simple enough to write by hand.
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct Rgb(pub u8, pub u8, pub u8);
+
+impl Rgb {
+    pub const BLACK: Rgb = Rgb(0, 0, 0);
+    pub const WHITE: Rgb = Rgb(255, 255, 255);
+
+    // Brightness as perceived, for grayscale and black and white images.
+    pub fn luminance(&self) -> u8 {
+        ((299 * self.0 as u32 + 587 * self.1 as u32 + 114 * self.2 as u32)
+            / 1000) as u8
+    }
+}
+
+// Netpbm flavours: black and white, grayscale or color. All are written in
+// their binary forms (P4, P5 and P6).
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Format {
+    Pbm,
+    Pgm,
+    Ppm
+}
+
+impl Format {
+    // The format a file name's extension asks for.
+    pub fn from_path(path: &str) -> Result<Format, Error> {
+        let lower = path.to_lowercase();
+        if lower.ends_with(".pbm") {
+            Ok(Format::Pbm)
+        } else if lower.ends_with(".pgm") {
+            Ok(Format::Pgm)
+        } else if lower.ends_with(".ppm") {
+            Ok(Format::Ppm)
+        } else {
+            Err(format_err!("{}: expected a .pbm, .pgm or .ppm file", path))
+        }
+    }
+}
+
+// Something drawn over a cell, e.g. a cursor: `ch` in text, `color` in
+// images.
+#[derive(Clone,Copy,Debug)]
+pub struct Highlight {
+    pub pos: IPos,
+    pub ch: char,
+    pub color: Rgb
+}
+
+// The cells from the top left corner to the bottom right one, inclusive.
+// A bottom right corner above or left of the top left makes an empty window.
+pub type Window = (IPos, IPos);
+
+fn size(window: Window) -> (usize, usize) {
+    let (min, max) = window;
+    let rows = if max.row < min.row { 0 } else { max.row - min.row + 1 };
+    let cols = if max.col < min.col { 0 } else { max.col - min.col + 1 };
+    (rows as usize, cols as usize)
+}
+
+fn highlight(highlights: &[Highlight], pos: IPos) -> Option<&Highlight> {
+    // Later highlights are drawn over earlier ones.
+    highlights.iter()
+              .rev()
+              .find(|h| h.pos.row == pos.row && h.pos.col == pos.col)
+}
+
+// Draw `window` as text, one line per row, with `cell` giving each
+// position's character.
+pub fn ascii<F>(window: Window, highlights: &[Highlight], cell: F) -> String
+        where F: Fn(IPos) -> char {
+    let (rows, cols) = size(window);
+    let mut text = String::with_capacity(rows * (cols + 1));
+    for row in 0..rows as isize {
+        for col in 0..cols as isize {
+            let pos = IPos::new(window.0.row + row, window.0.col + col);
+            text.push(match highlight(highlights, pos) {
+                Some(h) => h.ch,
+                None => cell(pos)
+            });
+        }
+        text.push('\n');
+    }
+    text
+}
+
+// Write `window` as an image, with `cell` giving each position's color.
+// Every cell becomes a `scale` by `scale` square of pixels.
+pub fn write_image<W, F>(mut writer: W, format: Format, window: Window,
+                         highlights: &[Highlight], scale: usize, cell: F)
+        -> Result<(), Error>
+        where W: Write, F: Fn(IPos) -> Rgb {
+    let (rows, cols) = size(window);
+    let scale = if scale == 0 { 1 } else { scale };
+    let (width, height) = (cols * scale, rows * scale);
+    match format {
+        Format::Pbm => write!(writer, "P4\n{} {}\n", width, height)?,
+        Format::Pgm => write!(writer, "P5\n{} {}\n255\n", width, height)?,
+        Format::Ppm => write!(writer, "P6\n{} {}\n255\n", width, height)?
+    }
+    let mut line: Vec<u8> = Vec::new();
+    for row in 0..rows as isize {
+        line.clear();
+        let colors = (0..cols as isize).map(|col| {
+            let pos = IPos::new(window.0.row + row, window.0.col + col);
+            match highlight(highlights, pos) {
+                Some(h) => h.color,
+                None => cell(pos)
+            }
+        }).collect::<Vec<Rgb>>();
+        match format {
+            Format::Pbm => {
+                // Eight pixels to a byte, 1 for black, each line padded to
+                // a whole byte.
+                line.resize((width + 7) / 8, 0);
+                for (x, color) in colors.iter().enumerate() {
+                    if color.luminance() < 128 {
+                        for x in x * scale..(x + 1) * scale {
+                            line[x / 8] |= 0x80 >> (x % 8);
+                        }
+                    }
+                }
+            },
+            Format::Pgm => for color in &colors {
+                for _ in 0..scale {
+                    line.push(color.luminance());
+                }
+            },
+            Format::Ppm => for color in &colors {
+                for _ in 0..scale {
+                    line.extend_from_slice(&[color.0, color.1, color.2]);
+                }
+            }
+        }
+        for _ in 0..scale {
+            writer.write_all(&line)?;
+        }
+    }
+    Ok(())
+}
+
+// Write an image file, in the format its extension names.
+pub fn save_image<F>(path: &str, window: Window, highlights: &[Highlight],
+                     scale: usize, cell: F) -> Result<(), Error>
+        where F: Fn(IPos) -> Rgb {
+    let format = Format::from_path(path)?;
+    let writer = BufWriter::new(File::create(path)?);
+    write_image(writer, format, window, highlights, scale, cell)
+}
+
+// Drawing for every kind of Grid.
+pub trait Render<T>: Grid<T> {
+    // The given window, or everything written to.
+    fn window(&self, window: Option<Window>) -> Window {
+        window.or(self.bounds())
+              .unwrap_or((IPos::new(0, 0), IPos::new(-1, -1)))
+    }
+
+    // Draw the grid as text. Without a window, draws bounds().
+    #[allow(dead_code)]
+    fn to_ascii<F>(&self, window: Option<Window>, highlights: &[Highlight],
+                   cell: F) -> String
+            where F: Fn(&T) -> char {
+        ascii(self.window(window), highlights, |pos| cell(&self[pos]))
+    }
+
+    // Write the grid as an image. Without a window, draws bounds().
+    #[allow(dead_code)]
+    fn write_image<W, F>(&self, writer: W, format: Format,
+                         window: Option<Window>, highlights: &[Highlight],
+                         scale: usize, cell: F) -> Result<(), Error>
+            where W: Write, F: Fn(&T) -> Rgb {
+        write_image(writer, format, self.window(window), highlights, scale,
+                    |pos| cell(&self[pos]))
+    }
+
+    // Write the grid to an image file, in the format its extension names.
+    #[allow(dead_code)]
+    fn save_image<F>(&self, path: &str, window: Option<Window>,
+                     highlights: &[Highlight], scale: usize, cell: F)
+            -> Result<(), Error>
+            where F: Fn(&T) -> Rgb {
+        save_image(path, self.window(window), highlights, scale,
+                   |pos| cell(&self[pos]))
+    }
+}
+
+impl<T, G: Grid<T>> Render<T> for G {}
+
+#[cfg(test)]
+mod tests {
+    use super::{Format,Highlight,Render,Rgb};
+    use util::grid::IPos;
+    use util::infinite_grid::InfiniteGrid;
+
+    fn grid() -> InfiniteGrid<bool> {
+        let mut grid = InfiniteGrid::new(false);
+        grid[ipos!(-1, -1)] = true;
+        grid[ipos!(0, 1)] = true;
+        grid
+    }
+
+    #[test]
+    fn ascii_with_cursor() {
+        let grid = grid();
+        let cursor = Highlight {
+            pos: ipos!(0, 0),
+            ch: '@',
+            color: Rgb(255, 0, 0)
+        };
+        let cell = |&b: &bool| if b { '#' } else { '.' };
+        assert_eq!(grid.to_ascii(None, &[cursor], cell), "#..\n.@#\n");
+        assert_eq!(grid.to_ascii(Some((ipos!(0, 0), ipos!(1, 1))), &[], cell),
+                   ".#\n..\n");
+        let empty: InfiniteGrid<bool> = InfiniteGrid::new(false);
+        assert_eq!(empty.to_ascii(None, &[], cell), "");
+    }
+
+    #[test]
+    fn netpbm_formats() {
+        let grid = grid();
+        let color = |&b: &bool| if b { Rgb::BLACK } else { Rgb(0, 0, 255) };
+        let image = |format, scale| {
+            let mut bytes = Vec::new();
+            grid.write_image(&mut bytes, format, None, &[], scale, color)
+                .unwrap();
+            bytes
+        };
+        // Blue is dark enough to come out black too.
+        assert_eq!(image(Format::Pbm, 1), b"P4\n3 2\n\xe0\xe0");
+        let pbm = image(Format::Pbm, 3);
+        assert_eq!(&pbm[..7], b"P4\n9 6\n");
+        assert_eq!(pbm.len(), 7 + 6 * 2);
+        assert_eq!(image(Format::Pgm, 1),
+                   b"P5\n3 2\n255\n\x00\x1d\x1d\x1d\x1d\x00".to_vec());
+        let ppm = image(Format::Ppm, 1);
+        assert_eq!(&ppm[..11], b"P6\n3 2\n255\n");
+        assert_eq!(&ppm[11..17], &[0, 0, 0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn formats_follow_extensions() {
+        assert_eq!(Format::from_path("a.PGM").unwrap(), Format::Pgm);
+        assert!(Format::from_path("a.png").is_err());
+    }
+}
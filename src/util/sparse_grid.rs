@@ -0,0 +1,57 @@
+use std::collections::HashMap;
+use std::ops::{Index,IndexMut};
+use std::vec;
+use util::grid::{extend_bounds,Grid,IPos};
+
+// A Grid keeping only the positions written to, in a HashMap. Memory grows
+// with the number of cells written, not the area they cover, at the cost of
+// a hash lookup on every access.
+#[allow(dead_code)]
+#[derive(Clone)]
+pub struct SparseGrid<T: Clone> {
+    cells: HashMap<IPos, T>,
+    default: T,
+    bounds: Option<(IPos, IPos)>
+}
+
+impl<T: Clone> SparseGrid<T> {
+    #[allow(dead_code)]
+    pub fn new(default: T) -> SparseGrid<T> {
+        SparseGrid {
+            cells: HashMap::new(),
+            default: default,
+            bounds: None
+        }
+    }
+}
+
+impl<T: Clone> Grid<T> for SparseGrid<T> {
+    fn bounds(&self) -> Option<(IPos, IPos)> {
+        self.bounds
+    }
+
+    // Only the positions written to.
+    fn iter_mut<'a>(&'a mut self) -> vec::IntoIter<(IPos, &'a mut T)> {
+        self.cells
+            .iter_mut()
+            .map(|(&pos, value)| (pos, value))
+            .collect::<Vec<(IPos, &mut T)>>()
+            .into_iter()
+    }
+}
+
+impl<T: Clone> Index<IPos> for SparseGrid<T> {
+    type Output = T;
+
+    fn index<'a>(&'a self, pos: IPos) -> &'a T {
+        self.cells.get(&pos).unwrap_or(&self.default)
+    }
+}
+
+impl<T: Clone> IndexMut<IPos> for SparseGrid<T> {
+    fn index_mut<'a>(&'a mut self, pos: IPos) -> &'a mut T {
+        self.bounds = extend_bounds(self.bounds, pos);
+        let default = &self.default;
+        self.cells.entry(pos).or_insert_with(|| default.clone())
+    }
+}